@@ -0,0 +1,23 @@
+CREATE TABLE auth_session(
+    id UUID PRIMARY KEY NOT NULL DEFAULT gen_random_uuid(),
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    -- Set when the session is revoked, e.g. when a rotated refresh token is reused.
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX auth_session_user_id_idx ON auth_session(user_id);
+
+CREATE TABLE refresh_token(
+    -- Only a SHA-256 digest of the opaque token is stored.
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    session_id UUID NOT NULL REFERENCES auth_session(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    -- Set once the token has been exchanged for a new one.
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX refresh_token_session_id_idx ON refresh_token(session_id);
@@ -0,0 +1,9 @@
+CREATE TABLE password_reset(
+    -- Only a SHA-256 digest of the emailed token is stored.
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX password_reset_user_id_idx ON password_reset(user_id);
@@ -0,0 +1,6 @@
+CREATE TABLE pending_email_change(
+    user_id INTEGER PRIMARY KEY REFERENCES gossip_user(id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    code TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+ALTER TABLE pending_email_verification
+    DROP COLUMN created_at,
+    DROP COLUMN attempts;
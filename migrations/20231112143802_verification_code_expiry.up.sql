@@ -0,0 +1,3 @@
+ALTER TABLE pending_email_verification
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+ALTER TABLE email_outbox
+    DROP COLUMN text_body;
+
+ALTER TABLE gossip_user
+    DROP COLUMN locale;
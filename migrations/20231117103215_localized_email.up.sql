@@ -0,0 +1,6 @@
+-- BCP 47 tag of the language emails are sent in, NULL for the server default.
+ALTER TABLE gossip_user
+    ADD COLUMN locale TEXT;
+
+ALTER TABLE email_outbox
+    ADD COLUMN text_body TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,3 @@
+DROP TABLE login_challenge;
+DROP TABLE recovery_code;
+DROP TABLE totp_authenticator;
@@ -0,0 +1,33 @@
+CREATE TABLE totp_authenticator(
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    -- Needed in the clear to compute codes, unlike the other secrets.
+    secret BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    -- Set once the user proved their authenticator works. Logins only require
+    -- a code from then on.
+    confirmed_at TIMESTAMPTZ,
+    -- Time step of the last accepted code, so that a code can't be replayed.
+    last_used_step BIGINT
+);
+
+CREATE TABLE recovery_code(
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    -- Only a SHA-256 digest of the code is stored.
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMPTZ,
+
+    PRIMARY KEY (user_id, code_hash)
+);
+
+-- A login that passed the password check, waiting for a second factor.
+CREATE TABLE login_challenge(
+    -- Only a SHA-256 digest of the token is stored.
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX login_challenge_user_id_idx ON login_challenge(user_id);
@@ -0,0 +1,9 @@
+-- A passwordless login waiting for the emailed code, or its link, to be used.
+CREATE TABLE email_login(
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    -- Only SHA-256 digests of the typed code and of the link token are stored.
+    code_hash TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+DROP TABLE external_identity;
+DROP TABLE oidc_login;
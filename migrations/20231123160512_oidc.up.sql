@@ -0,0 +1,22 @@
+-- A login started at an OpenID Connect provider, waiting for its callback.
+CREATE TABLE oidc_login(
+    -- Only a SHA-256 digest of the `state` parameter is stored.
+    state_hash TEXT PRIMARY KEY NOT NULL,
+    provider TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- An account at an OpenID Connect provider, identified by its `sub` claim.
+CREATE TABLE external_identity(
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX external_identity_user_id_idx ON external_identity(user_id);
@@ -0,0 +1,7 @@
+-- Where a session was started from, shown to the user in their session list.
+ALTER TABLE auth_session
+    ADD COLUMN device_name TEXT,
+    ADD COLUMN user_agent TEXT,
+    ADD COLUMN ip_address TEXT,
+    -- Updated at most every few minutes, see `auth::extractors`.
+    ADD COLUMN last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now();
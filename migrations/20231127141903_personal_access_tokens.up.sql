@@ -0,0 +1,17 @@
+-- A long-lived token a user minted for a bot or script, limited to its scopes.
+CREATE TABLE personal_access_token(
+    id UUID PRIMARY KEY NOT NULL DEFAULT gen_random_uuid(),
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- Only a SHA-256 digest of the token is stored, it is shown once.
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Never expires when NULL.
+    expires_at TIMESTAMPTZ,
+    -- Updated at most every few minutes, see `auth::extractors`.
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX personal_access_token_user_id_idx ON personal_access_token(user_id);
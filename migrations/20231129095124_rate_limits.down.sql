@@ -0,0 +1,2 @@
+DROP TABLE rate_limit_failure;
+DROP TABLE rate_limit_bucket;
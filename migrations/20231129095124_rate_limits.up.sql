@@ -0,0 +1,21 @@
+-- A token bucket of the rate limiter, when `RATE_LIMIT_STORAGE` is postgres.
+CREATE TABLE rate_limit_bucket(
+    key TEXT PRIMARY KEY NOT NULL,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    -- When the bucket is full again, and can be forgotten.
+    full_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limit_bucket_full_at_idx ON rate_limit_bucket(full_at);
+
+-- Recent failures of a key, such as failed logins to an email.
+CREATE TABLE rate_limit_failure(
+    key TEXT PRIMARY KEY NOT NULL,
+    failures INTEGER NOT NULL,
+    locked_until TIMESTAMPTZ,
+    -- When the failures are forgotten.
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limit_failure_expires_at_idx ON rate_limit_failure(expires_at);
@@ -0,0 +1,2 @@
+ALTER TABLE gossip_user
+    DROP COLUMN updated_at;
@@ -0,0 +1,3 @@
+-- Last change of the username or bio.
+ALTER TABLE gossip_user
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
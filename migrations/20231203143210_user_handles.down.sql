@@ -0,0 +1,5 @@
+DROP TABLE previous_handle;
+
+ALTER TABLE gossip_user
+    DROP COLUMN handle,
+    DROP COLUMN handle_changed_at;
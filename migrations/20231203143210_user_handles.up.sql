@@ -0,0 +1,17 @@
+-- Unique public name, such as `@ann`, compared case-insensitively. Unset until
+-- the user picks one.
+ALTER TABLE gossip_user
+    ADD COLUMN handle TEXT,
+    ADD COLUMN handle_changed_at TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX gossip_user_handle_key ON gossip_user(lower(handle));
+
+-- A handle a user changed away from. Until it expires it redirects to their
+-- current handle, and nobody else can take it.
+CREATE TABLE previous_handle(
+    handle TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES gossip_user(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX previous_handle_handle_key ON previous_handle(lower(handle));
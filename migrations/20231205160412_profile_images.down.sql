@@ -0,0 +1,5 @@
+ALTER TABLE gossip_user
+    DROP COLUMN avatar_key,
+    DROP COLUMN avatar,
+    DROP COLUMN banner_key,
+    DROP COLUMN banner;
@@ -0,0 +1,7 @@
+-- Profile images, each in all of its sizes. `*_key` is the prefix of their
+-- blob keys, and `avatar` and `banner` list their URLs and dimensions.
+ALTER TABLE gossip_user
+    ADD COLUMN avatar_key TEXT,
+    ADD COLUMN avatar JSONB,
+    ADD COLUMN banner_key TEXT,
+    ADD COLUMN banner JSONB;
@@ -0,0 +1,5 @@
+DROP INDEX gossip_user_username_trgm_idx;
+DROP INDEX gossip_user_handle_trgm_idx;
+
+ALTER TABLE gossip_user
+    DROP COLUMN blocked_at;
@@ -0,0 +1,9 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Set by operators on accounts hidden from discovery, such as search.
+ALTER TABLE gossip_user
+    ADD COLUMN blocked_at TIMESTAMPTZ;
+
+-- Trigram indexes, for the similarity and prefix matches of user search.
+CREATE INDEX gossip_user_username_trgm_idx ON gossip_user USING GIN (lower(username) gin_trgm_ops);
+CREATE INDEX gossip_user_handle_trgm_idx ON gossip_user USING GIN (lower(handle) gin_trgm_ops);
@@ -0,0 +1,5 @@
+ALTER TABLE gossip_user
+    DROP COLUMN email_visibility,
+    DROP COLUMN bio_visibility;
+
+DROP TYPE visibility;
@@ -0,0 +1,7 @@
+-- Who can see a part of a profile. The user always can.
+CREATE TYPE visibility AS ENUM ('everyone', 'signed_in', 'nobody');
+
+ALTER TABLE gossip_user
+    -- Who can find the user by their email address.
+    ADD COLUMN email_visibility visibility NOT NULL DEFAULT 'signed_in',
+    ADD COLUMN bio_visibility visibility NOT NULL DEFAULT 'everyone';
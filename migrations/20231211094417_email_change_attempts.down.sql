@@ -0,0 +1,2 @@
+ALTER TABLE pending_email_change
+    DROP COLUMN attempts;
@@ -0,0 +1,2 @@
+ALTER TABLE pending_email_change
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
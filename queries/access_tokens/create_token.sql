@@ -0,0 +1,3 @@
+INSERT INTO personal_access_token (user_id, name, token_hash, scopes, expires_at)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING id, name, scopes, created_at, expires_at, last_used_at
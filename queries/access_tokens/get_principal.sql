@@ -0,0 +1,13 @@
+SELECT
+    gossip_user.id AS user_id,
+    is_verified,
+    personal_access_token.id AS token_id,
+    scopes,
+    last_used_at
+FROM gossip_user
+JOIN personal_access_token ON
+    personal_access_token.user_id = gossip_user.id
+WHERE
+    token_hash = $1
+    AND revoked_at IS NULL
+    AND (expires_at IS NULL OR expires_at > now())
@@ -0,0 +1,5 @@
+-- Expired tokens are listed too, until they are revoked.
+SELECT id, name, scopes, created_at, expires_at, last_used_at
+FROM personal_access_token
+WHERE user_id = $1 AND revoked_at IS NULL
+ORDER BY created_at DESC
@@ -0,0 +1,4 @@
+UPDATE personal_access_token
+SET revoked_at = now()
+WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+RETURNING id
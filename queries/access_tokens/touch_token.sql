@@ -0,0 +1,3 @@
+UPDATE personal_access_token
+SET last_used_at = now()
+WHERE id = $1
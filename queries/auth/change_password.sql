@@ -0,0 +1,19 @@
+WITH
+    _ AS (
+        UPDATE auth_session
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+    ),
+    __ AS (
+        DELETE FROM password_reset
+        WHERE user_id = $1
+    ),
+    ___ AS (
+        UPDATE personal_access_token
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+    )
+
+UPDATE gossip_user
+SET password_hash = $2
+WHERE id = $1
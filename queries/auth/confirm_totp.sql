@@ -0,0 +1,4 @@
+UPDATE totp_authenticator
+SET confirmed_at = now(), last_used_step = $2
+WHERE user_id = $1 AND confirmed_at IS NULL
+RETURNING user_id
@@ -0,0 +1,7 @@
+-- Deleting the login is what makes the code single-use.
+DELETE FROM email_login
+WHERE
+    user_id = $1
+    AND code_hash = $2
+    AND created_at > now() - make_interval(secs => $3)
+RETURNING user_id
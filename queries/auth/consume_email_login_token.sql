@@ -0,0 +1,4 @@
+-- Deleting the login is what makes the link single-use.
+DELETE FROM email_login
+WHERE token_hash = $1 AND created_at > now() - make_interval(secs => $2)
+RETURNING user_id
@@ -0,0 +1,3 @@
+DELETE FROM login_challenge
+WHERE token_hash = $1 AND expires_at > now()
+RETURNING user_id
@@ -0,0 +1,12 @@
+-- A new code replaces the previous one, unless it was sent less than the
+-- cooldown ago.
+INSERT INTO email_login (user_id, code_hash, token_hash)
+VALUES ($1, $2, $3)
+ON CONFLICT (user_id) DO UPDATE
+SET
+    code_hash = EXCLUDED.code_hash,
+    token_hash = EXCLUDED.token_hash,
+    created_at = now(),
+    attempts = 0
+WHERE email_login.created_at <= now() - make_interval(secs => $4)
+RETURNING user_id
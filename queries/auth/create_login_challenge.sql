@@ -0,0 +1,2 @@
+INSERT INTO login_challenge (token_hash, user_id, expires_at)
+VALUES ($1, $2, $3)
@@ -0,0 +1,9 @@
+WITH
+    -- Requesting a new reset invalidates any earlier one.
+    _ AS (
+        DELETE FROM password_reset
+        WHERE user_id = $1
+    )
+
+INSERT INTO password_reset (token_hash, user_id, expires_at)
+VALUES ($2, $1, $3)
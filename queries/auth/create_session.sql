@@ -0,0 +1,10 @@
+WITH session AS (
+    INSERT INTO auth_session (user_id, device_name, user_agent, ip_address)
+    VALUES ($1, $2, $3, $4)
+    RETURNING id
+)
+
+INSERT INTO refresh_token (token_hash, session_id, expires_at)
+SELECT $5, id, $6
+FROM session
+RETURNING session_id
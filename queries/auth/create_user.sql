@@ -1,7 +1,7 @@
 
 WITH insert_result AS(
-    INSERT INTO gossip_user (email, password_hash, username)
-    VALUES ($1, $2, $3)
+    INSERT INTO gossip_user (email, password_hash, username, locale)
+    VALUES ($1, $2, $3, $5)
  
     -- Account creation is idempotent for unverified accounts,
     -- if the email is taken, but the account is not verified, the creation should pass.
@@ -10,7 +10,8 @@ WITH insert_result AS(
         DO UPDATE
         SET
             password_hash = $2,
-            username = $3
+            username = $3,
+            locale = $5
         WHERE gossip_user.is_verified = FALSE
 
     RETURNING id
@@ -21,5 +22,8 @@ SELECT id, $4
 FROM insert_result
 ON CONFLICT (user_id)
     DO UPDATE
-    SET code = $4
+    SET
+        code = $4,
+        created_at = now(),
+        attempts = 0
 RETURNING user_id 
\ No newline at end of file
@@ -0,0 +1,8 @@
+WITH
+    _ AS (
+        DELETE FROM recovery_code
+        WHERE user_id = $1
+    )
+
+DELETE FROM totp_authenticator
+WHERE user_id = $1
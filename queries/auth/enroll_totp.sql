@@ -0,0 +1,7 @@
+-- Enrolling again replaces an unconfirmed secret, but never a confirmed one.
+INSERT INTO totp_authenticator (user_id, secret)
+VALUES ($1, $2)
+ON CONFLICT (user_id) DO UPDATE
+SET secret = EXCLUDED.secret, created_at = now()
+WHERE totp_authenticator.confirmed_at IS NULL
+RETURNING user_id
@@ -0,0 +1,6 @@
+SELECT user_id, created_at, attempts
+FROM email_login
+JOIN gossip_user ON
+    gossip_user.id = email_login.user_id
+WHERE
+    gossip_user.email = $1 AND gossip_user.is_verified = TRUE
@@ -0,0 +1,4 @@
+SELECT gossip_user.email
+FROM login_challenge
+JOIN gossip_user ON gossip_user.id = login_challenge.user_id
+WHERE login_challenge.token_hash = $1 AND login_challenge.expires_at > now()
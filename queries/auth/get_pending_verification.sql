@@ -1,4 +1,4 @@
-SELECT user_id, code
+SELECT user_id, username, locale, code, created_at, attempts
 FROM pending_email_verification
 JOIN gossip_user ON
     gossip_user.id = pending_email_verification.user_id
@@ -0,0 +1,12 @@
+SELECT
+    gossip_user.id AS user_id,
+    is_verified,
+    auth_session.id AS session_id,
+    auth_session.last_seen_at
+FROM gossip_user
+JOIN auth_session ON
+    auth_session.user_id = gossip_user.id
+WHERE
+    auth_session.id = $1
+    AND gossip_user.id = $2
+    AND auth_session.revoked_at IS NULL
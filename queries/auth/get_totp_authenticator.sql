@@ -0,0 +1,4 @@
+SELECT
+    secret, confirmed_at IS NOT NULL AS "is_confirmed!"
+FROM totp_authenticator
+WHERE user_id = $1
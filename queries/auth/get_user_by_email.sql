@@ -1,4 +1,4 @@
 SELECT
-    id, username, email, password_hash, is_verified
+    id, username, email, password_hash, is_verified, locale
 FROM gossip_user
 WHERE email = $1
@@ -0,0 +1,22 @@
+-- Sessions that can still be refreshed, most recently used first.
+SELECT
+    id,
+    device_name,
+    user_agent,
+    ip_address,
+    created_at,
+    last_seen_at,
+    id = $2 AS "current!"
+FROM auth_session
+WHERE
+    user_id = $1
+    AND revoked_at IS NULL
+    AND EXISTS (
+        SELECT 1
+        FROM refresh_token
+        WHERE
+            refresh_token.session_id = auth_session.id
+            AND used_at IS NULL
+            AND expires_at > now()
+    )
+ORDER BY last_seen_at DESC
@@ -0,0 +1,6 @@
+-- Counting the attempt before the code is checked keeps concurrent guesses
+-- from exceeding the limit.
+UPDATE login_challenge
+SET attempts = attempts + 1
+WHERE token_hash = $1 AND expires_at > now() AND attempts < $2
+RETURNING user_id
@@ -0,0 +1,6 @@
+-- Counting the attempt before the code is compared keeps concurrent guesses
+-- from exceeding the limit.
+UPDATE pending_email_verification
+SET attempts = attempts + 1
+WHERE user_id = $1 AND attempts < $2
+RETURNING attempts
@@ -0,0 +1,9 @@
+-- The cooldown is re-checked here so that concurrent requests cannot send
+-- more than one code per window.
+UPDATE pending_email_verification
+SET
+    code = $2,
+    created_at = now(),
+    attempts = 0
+WHERE user_id = $1 AND created_at <= now() - make_interval(secs => $3)
+RETURNING user_id
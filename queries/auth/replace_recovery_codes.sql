@@ -0,0 +1,9 @@
+WITH
+    _ AS (
+        DELETE FROM recovery_code
+        WHERE user_id = $1
+    )
+
+INSERT INTO recovery_code (user_id, code_hash)
+SELECT $1, code_hash
+FROM UNNEST($2::TEXT[]) AS code_hash
@@ -0,0 +1,26 @@
+WITH
+    reset AS (
+        -- Consumes the used token along with any other outstanding reset of the user.
+        DELETE FROM password_reset
+        WHERE user_id IN (
+            SELECT user_id
+            FROM password_reset
+            WHERE token_hash = $1 AND expires_at > now()
+        )
+        RETURNING user_id
+    ),
+    _ AS (
+        UPDATE auth_session
+        SET revoked_at = now()
+        WHERE user_id IN (SELECT user_id FROM reset) AND revoked_at IS NULL
+    ),
+    __ AS (
+        UPDATE personal_access_token
+        SET revoked_at = now()
+        WHERE user_id IN (SELECT user_id FROM reset) AND revoked_at IS NULL
+    )
+
+UPDATE gossip_user
+SET password_hash = $2
+WHERE id IN (SELECT user_id FROM reset)
+RETURNING id
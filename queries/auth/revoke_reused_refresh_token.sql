@@ -0,0 +1,12 @@
+-- A refresh token that has already been rotated is being presented again.
+-- Either the legitimate client or an attacker holds a stale copy, so the
+-- whole session (token family) is revoked.
+UPDATE auth_session
+SET revoked_at = now()
+FROM refresh_token
+WHERE
+    refresh_token.session_id = auth_session.id
+    AND refresh_token.token_hash = $1
+    AND refresh_token.used_at IS NOT NULL
+    AND auth_session.revoked_at IS NULL
+RETURNING auth_session.id
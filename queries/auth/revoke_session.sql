@@ -0,0 +1,3 @@
+UPDATE auth_session
+SET revoked_at = now()
+WHERE id = $1 AND revoked_at IS NULL
@@ -0,0 +1,28 @@
+WITH
+    used AS (
+        -- Only a live token may be exchanged, and only once.
+        UPDATE refresh_token
+        SET used_at = now()
+        FROM auth_session
+        WHERE
+            refresh_token.session_id = auth_session.id
+            AND refresh_token.token_hash = $1
+            AND refresh_token.used_at IS NULL
+            AND refresh_token.expires_at > now()
+            AND auth_session.revoked_at IS NULL
+        RETURNING refresh_token.session_id, auth_session.user_id
+    ),
+    _ AS (
+        INSERT INTO refresh_token (token_hash, session_id, expires_at)
+        SELECT $2, session_id, $3
+        FROM used
+    ),
+    __ AS (
+        UPDATE auth_session
+        SET last_seen_at = now()
+        FROM used
+        WHERE auth_session.id = used.session_id
+    )
+
+SELECT session_id, user_id
+FROM used
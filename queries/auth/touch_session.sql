@@ -0,0 +1,3 @@
+UPDATE auth_session
+SET last_seen_at = now()
+WHERE id = $1
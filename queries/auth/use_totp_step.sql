@@ -0,0 +1,7 @@
+-- Accepting only later steps keeps a code from being used twice.
+UPDATE totp_authenticator
+SET last_used_step = $2
+WHERE user_id = $1
+    AND confirmed_at IS NOT NULL
+    AND (last_used_step IS NULL OR last_used_step < $2)
+RETURNING user_id
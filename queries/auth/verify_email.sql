@@ -10,6 +10,6 @@ WITH
         WHERE user_id IN (SELECT id FROM update_result)
     )
 
-SELECT id, username, email, password_hash, is_verified
+SELECT id, username, email, password_hash, is_verified, locale
 FROM gossip_user
 WHERE id IN (SELECT id FROM update_result)
\ No newline at end of file
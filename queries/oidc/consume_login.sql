@@ -0,0 +1,4 @@
+-- Deleting the login is what makes its state single-use.
+DELETE FROM oidc_login
+WHERE state_hash = $1 AND provider = $2 AND expires_at > now()
+RETURNING code_verifier, nonce
@@ -0,0 +1,2 @@
+INSERT INTO oidc_login (state_hash, provider, code_verifier, nonce, expires_at)
+VALUES ($1, $2, $3, $4, $5)
@@ -0,0 +1,3 @@
+SELECT user_id
+FROM external_identity
+WHERE provider = $1 AND subject = $2
@@ -0,0 +1,36 @@
+WITH
+    account AS (
+        INSERT INTO gossip_user (email, password_hash, username, is_verified)
+        VALUES ($3, $4, $5, TRUE)
+
+        -- The provider verified the address, so it can claim an existing account.
+        -- An unverified account may have been registered by someone else though,
+        -- whose password and name must not survive the takeover.
+        ON CONFLICT (email)
+            DO UPDATE
+            SET
+                is_verified = TRUE,
+                password_hash = CASE
+                    WHEN gossip_user.is_verified THEN gossip_user.password_hash
+                    ELSE EXCLUDED.password_hash
+                END,
+                username = CASE
+                    WHEN gossip_user.is_verified THEN gossip_user.username
+                    ELSE EXCLUDED.username
+                END
+
+        RETURNING id
+    ),
+    _ AS (
+        DELETE FROM pending_email_verification
+        WHERE user_id IN (SELECT id FROM account)
+    ),
+    __ AS (
+        INSERT INTO external_identity (provider, subject, user_id)
+        SELECT $1, $2, id
+        FROM account
+        ON CONFLICT (provider, subject) DO NOTHING
+    )
+
+SELECT id
+FROM account
@@ -0,0 +1,13 @@
+-- Claimed emails are leased by pushing their next attempt into the future, so
+-- that other instances skip them and a crashed worker's claims expire.
+UPDATE email_outbox
+SET next_attempt_at = now() + make_interval(secs => $2)
+WHERE id IN (
+    SELECT id
+    FROM email_outbox
+    WHERE status = 'pending' AND next_attempt_at <= now()
+    ORDER BY next_attempt_at
+    LIMIT $1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING id, to_name, to_address, subject, html_body, text_body, attempts
@@ -0,0 +1,2 @@
+INSERT INTO email_outbox (to_name, to_address, subject, html_body, text_body)
+VALUES ($1, $2, $3, $4, $5)
@@ -0,0 +1,5 @@
+SELECT id, to_address, subject, attempts, last_error, created_at
+FROM email_outbox
+WHERE status = 'dead'
+ORDER BY created_at DESC
+LIMIT $1
@@ -0,0 +1,6 @@
+SELECT
+    count(*) FILTER (WHERE status = 'pending') AS "pending!",
+    count(*) FILTER (WHERE status = 'sent') AS "sent!",
+    count(*) FILTER (WHERE status = 'dead') AS "dead!",
+    min(created_at) FILTER (WHERE status = 'pending') AS oldest_pending_at
+FROM email_outbox
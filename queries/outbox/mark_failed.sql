@@ -0,0 +1,8 @@
+UPDATE email_outbox
+SET
+    status = CASE WHEN attempts + 1 >= $3 THEN 'dead' ELSE 'pending' END,
+    attempts = attempts + 1,
+    last_error = $2,
+    next_attempt_at = now() + make_interval(secs => $4)
+WHERE id = $1
+RETURNING status
@@ -0,0 +1,7 @@
+UPDATE email_outbox
+SET
+    status = 'sent',
+    attempts = attempts + 1,
+    last_error = NULL,
+    sent_at = now()
+WHERE id = $1
@@ -0,0 +1,7 @@
+UPDATE email_outbox
+SET
+    status = 'pending',
+    attempts = 0,
+    next_attempt_at = now()
+WHERE id = $1 AND status = 'dead'
+RETURNING id
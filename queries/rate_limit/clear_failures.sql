@@ -0,0 +1,2 @@
+DELETE FROM rate_limit_failure
+WHERE key = $1
@@ -0,0 +1,3 @@
+SELECT CEIL(EXTRACT(EPOCH FROM locked_until - now()))::BIGINT AS "seconds!"
+FROM rate_limit_failure
+WHERE key = $1 AND locked_until > now()
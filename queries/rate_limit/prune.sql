@@ -0,0 +1,8 @@
+WITH
+    _ AS (
+        DELETE FROM rate_limit_bucket
+        WHERE full_at <= now()
+    )
+
+DELETE FROM rate_limit_failure
+WHERE expires_at <= now()
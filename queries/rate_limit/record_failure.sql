@@ -0,0 +1,26 @@
+WITH
+    counted AS (
+        SELECT COALESCE(
+            (
+                SELECT failures
+                FROM rate_limit_failure
+                WHERE key = $1 AND expires_at > now()
+                FOR UPDATE
+            ),
+            0
+        ) + 1 AS failures
+    )
+
+INSERT INTO rate_limit_failure (key, failures, locked_until, expires_at)
+SELECT
+    $1,
+    -- The count starts over once locked out.
+    CASE WHEN failures >= $2 THEN 0 ELSE failures END,
+    CASE WHEN failures >= $2 THEN now() + make_interval(secs => $3) END,
+    now() + make_interval(secs => $3)
+FROM counted
+ON CONFLICT (key) DO UPDATE
+SET
+    failures = EXCLUDED.failures,
+    locked_until = COALESCE(EXCLUDED.locked_until, rate_limit_failure.locked_until),
+    expires_at = EXCLUDED.expires_at
@@ -0,0 +1,32 @@
+WITH
+    -- Locked, so that concurrent requests take from the bucket one by one.
+    bucket AS (
+        SELECT LEAST($2, tokens + EXTRACT(EPOCH FROM now() - updated_at)::FLOAT8 * $3) AS tokens
+        FROM rate_limit_bucket
+        WHERE key = $1
+        FOR UPDATE
+    ),
+    refilled AS (
+        SELECT COALESCE((SELECT tokens FROM bucket), $2) AS tokens
+    ),
+    taken AS (
+        INSERT INTO rate_limit_bucket (key, tokens, updated_at, full_at)
+        SELECT
+            $1,
+            tokens - 1,
+            now(),
+            now() + make_interval(secs => ($2 - tokens + 1) / $3)
+        FROM refilled
+        WHERE tokens >= 1
+        ON CONFLICT (key) DO UPDATE
+        SET
+            tokens = EXCLUDED.tokens,
+            updated_at = EXCLUDED.updated_at,
+            full_at = EXCLUDED.full_at
+        RETURNING key
+    )
+
+SELECT
+    tokens AS "tokens!",
+    EXISTS (SELECT 1 FROM taken) AS "taken!"
+FROM refilled
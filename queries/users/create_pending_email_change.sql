@@ -0,0 +1,13 @@
+-- The new address may still be held by an unverified registration, but not
+-- by a verified account, matching the semantics of `is_email_taken`.
+INSERT INTO pending_email_change (user_id, new_email, code)
+SELECT $1, $2, $3
+WHERE NOT EXISTS (SELECT 1 FROM gossip_user WHERE email = $2 AND is_verified = TRUE)
+ON CONFLICT (user_id)
+    DO UPDATE
+    SET
+        new_email = $2,
+        code = $3,
+        created_at = now(),
+        attempts = 0
+RETURNING user_id
@@ -0,0 +1,8 @@
+INSERT INTO previous_handle (handle, user_id, expires_at)
+VALUES ($1, $2, $3)
+ON CONFLICT ((lower(handle)))
+    DO UPDATE
+    SET
+        handle = $1,
+        user_id = $2,
+        expires_at = $3
@@ -0,0 +1,5 @@
+-- Matching on the address and code guards against the pending change having
+-- been replaced since the code was checked.
+DELETE FROM pending_email_change
+WHERE user_id = $1 AND new_email = $2 AND code = $3
+RETURNING new_email
@@ -0,0 +1,13 @@
+WITH
+    unverified AS (
+        SELECT id
+        FROM gossip_user
+        WHERE email = $1 AND is_verified = FALSE
+    ),
+    _ AS (
+        DELETE FROM pending_email_verification
+        WHERE user_id IN (SELECT id FROM unverified)
+    )
+
+DELETE FROM gossip_user
+WHERE id IN (SELECT id FROM unverified)
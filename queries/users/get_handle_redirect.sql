@@ -0,0 +1,7 @@
+SELECT gossip_user.handle AS "handle!"
+FROM previous_handle
+JOIN gossip_user ON gossip_user.id = previous_handle.user_id
+WHERE lower(previous_handle.handle) = lower($1)
+    AND previous_handle.expires_at > now()
+    AND gossip_user.handle IS NOT NULL
+    AND gossip_user.is_verified = TRUE
@@ -0,0 +1,3 @@
+SELECT user_id, new_email, code, created_at, attempts
+FROM pending_email_change
+WHERE user_id = $1
@@ -0,0 +1,5 @@
+SELECT
+    email_visibility AS "email_lookup: Visibility",
+    bio_visibility AS "bio: Visibility"
+FROM gossip_user
+WHERE id = $1 AND is_verified = TRUE
@@ -0,0 +1,12 @@
+SELECT
+    id, username, handle,
+    -- $2 is the user viewing the profile, if signed in.
+    CASE
+        WHEN bio_visibility = 'everyone' OR id = $2 THEN bio
+        WHEN bio_visibility = 'signed_in' AND $2 IS NOT NULL THEN bio
+    END AS bio,
+    avatar AS "avatar: Json<Vec<ProfileImage>>",
+    banner AS "banner: Json<Vec<ProfileImage>>",
+    updated_at
+FROM gossip_user
+WHERE lower(handle) = lower($1) AND is_verified = TRUE
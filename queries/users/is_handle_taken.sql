@@ -0,0 +1,6 @@
+SELECT
+    EXISTS(SELECT 1 FROM gossip_user WHERE lower(handle) = lower($1))
+    OR EXISTS(
+        SELECT 1 FROM previous_handle
+        WHERE lower(handle) = lower($1) AND expires_at > now()
+    ) AS "taken!"
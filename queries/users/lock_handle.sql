@@ -0,0 +1,4 @@
+SELECT handle, handle_changed_at
+FROM gossip_user
+WHERE id = $1 AND is_verified = TRUE
+FOR UPDATE
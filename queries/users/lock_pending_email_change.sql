@@ -0,0 +1,6 @@
+-- Locked so that concurrent requests cannot send more than one code per
+-- cooldown.
+SELECT created_at
+FROM pending_email_change
+WHERE user_id = $1
+FOR UPDATE
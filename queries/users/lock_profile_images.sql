@@ -0,0 +1,4 @@
+SELECT avatar_key, banner_key
+FROM gossip_user
+WHERE id = $1 AND is_verified = TRUE
+FOR UPDATE
@@ -0,0 +1,4 @@
+-- Lets a user take back a handle they had, and frees expired ones.
+DELETE FROM previous_handle
+WHERE lower(handle) = lower($2)
+    AND (user_id = $1 OR expires_at <= now())
@@ -0,0 +1,44 @@
+-- Verified users whose username or handle starts with or resembles the query
+-- $1, best matches first. $2 is the query as a LIKE prefix pattern, $3 and $4
+-- the rank and id of the last result of the previous page, and $6 the user
+-- searching, if signed in.
+SELECT
+    id, username, handle,
+    CASE
+        WHEN bio_visibility = 'everyone' OR id = $6 THEN bio
+        WHEN bio_visibility = 'signed_in' AND $6 IS NOT NULL THEN bio
+    END AS bio,
+    avatar AS "avatar: Json<Vec<ProfileImage>>",
+    banner AS "banner: Json<Vec<ProfileImage>>",
+    updated_at,
+    rank AS "rank!"
+FROM (
+    SELECT
+        *,
+        (
+            GREATEST(
+                word_similarity($1, lower(username)),
+                similarity($1, lower(handle))
+            )
+            -- Prefixes count more than resemblance, and handles more than
+            -- usernames.
+            + CASE
+                WHEN lower(handle) = $1 THEN 3
+                WHEN lower(handle) LIKE $2 THEN 2
+                WHEN lower(username) LIKE $2 THEN 1
+                ELSE 0
+            END
+        )::REAL AS rank
+    FROM gossip_user
+    WHERE is_verified = TRUE
+        AND blocked_at IS NULL
+        AND (
+            $1 <% lower(username)
+            OR lower(handle) % $1
+            OR lower(username) LIKE $2
+            OR lower(handle) LIKE $2
+        )
+) AS matches
+WHERE $3::REAL IS NULL OR (rank, -id) < ($3, -$4::INTEGER)
+ORDER BY rank DESC, id
+LIMIT $5
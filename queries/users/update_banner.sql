@@ -0,0 +1,11 @@
+UPDATE gossip_user
+SET
+    banner_key = $2,
+    banner = $3,
+    updated_at = now()
+WHERE id = $1
+RETURNING
+    id, username, handle, bio AS "bio?",
+    avatar AS "avatar: Json<Vec<ProfileImage>>",
+    banner AS "banner: Json<Vec<ProfileImage>>",
+    updated_at
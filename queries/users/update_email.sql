@@ -0,0 +1,3 @@
+UPDATE gossip_user
+SET email = $2
+WHERE id = $1
@@ -0,0 +1,15 @@
+UPDATE gossip_user
+SET
+    handle = $2,
+    -- Changing only the case doesn't count towards the cooldown.
+    handle_changed_at = CASE
+        WHEN lower(handle) = lower($2) THEN handle_changed_at
+        ELSE now()
+    END,
+    updated_at = now()
+WHERE id = $1
+RETURNING
+    id, username, handle, bio AS "bio?",
+    avatar AS "avatar: Json<Vec<ProfileImage>>",
+    banner AS "banner: Json<Vec<ProfileImage>>",
+    updated_at
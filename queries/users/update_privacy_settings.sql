@@ -0,0 +1,8 @@
+UPDATE gossip_user
+SET
+    email_visibility = COALESCE($2, email_visibility),
+    bio_visibility = COALESCE($3, bio_visibility)
+WHERE id = $1 AND is_verified = TRUE
+RETURNING
+    email_visibility AS "email_lookup: Visibility",
+    bio_visibility AS "bio: Visibility"
@@ -0,0 +1,15 @@
+UPDATE gossip_user
+SET
+    username = COALESCE($2, username),
+    bio = COALESCE($3, bio),
+    updated_at = CASE
+        WHEN (username, bio) IS DISTINCT FROM (COALESCE($2, username), COALESCE($3, bio))
+        THEN now()
+        ELSE updated_at
+    END
+WHERE id = $1 AND is_verified = TRUE
+RETURNING
+    id, username, handle, bio AS "bio?",
+    avatar AS "avatar: Json<Vec<ProfileImage>>",
+    banner AS "banner: Json<Vec<ProfileImage>>",
+    updated_at
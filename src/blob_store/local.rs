@@ -0,0 +1,109 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use anyhow::bail;
+use axum::async_trait;
+
+use super::{is_valid_key, BlobStore};
+
+/// Keeps blobs as files in a directory, which the app serves itself under
+/// `/blobs` unless `base_url` points elsewhere, such as to a CDN.
+#[derive(Debug)]
+pub struct LocalBlobStore {
+    dir: PathBuf,
+    base_url: String,
+}
+
+impl LocalBlobStore {
+    pub fn new(dir: PathBuf, base_url: String) -> LocalBlobStore {
+        LocalBlobStore {
+            dir,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        if !is_valid_key(key) {
+            bail!("Invalid blob key {key:?}");
+        }
+
+        Ok(self.dir.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, _content_type: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Written aside first, so the blob is never served half-written.
+        let partial_path = path.with_extension("partial");
+        tokio::fs::write(&partial_path, data).await?;
+        tokio::fs::rename(&partial_path, &path).await?;
+
+        Ok(())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_dir_all(self.path(prefix)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_puts_and_deletes_blobs() {
+        let dir = std::env::temp_dir().join(format!("gossip-blobs-{}", Uuid::new_v4()));
+        let store = LocalBlobStore::new(dir.clone(), "http://cdn.test/blobs/".to_owned());
+
+        store
+            .put("avatars/3/a/64.jpg", "image/jpeg", b"small".to_vec())
+            .await
+            .unwrap();
+        store
+            .put("avatars/3/a/512.jpg", "image/jpeg", b"large".to_vec())
+            .await
+            .unwrap();
+        store
+            .put("avatars/3/b/64.jpg", "image/jpeg", b"other".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(dir.join("avatars/3/a/64.jpg")).unwrap(),
+            b"small"
+        );
+        assert_eq!(
+            store.url("avatars/3/a/64.jpg"),
+            "http://cdn.test/blobs/avatars/3/a/64.jpg"
+        );
+
+        store.delete_prefix("avatars/3/a").await.unwrap();
+        assert!(!dir.join("avatars/3/a").exists());
+        assert!(dir.join("avatars/3/b/64.jpg").exists());
+
+        // Deleting what is gone already is fine.
+        store.delete_prefix("avatars/3/a").await.unwrap();
+
+        assert!(store
+            .put("../escape.jpg", "image/jpeg", Vec::new())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
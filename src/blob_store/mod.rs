@@ -0,0 +1,62 @@
+mod local;
+
+use std::{fmt::Debug, sync::Arc};
+
+use axum::async_trait;
+
+use crate::config::Config;
+
+pub use local::LocalBlobStore;
+
+/// Storage of the public files of users, such as their profile images, under
+/// keys like `avatars/3/<uuid>/512.jpg`.
+#[async_trait]
+pub trait BlobStore: Debug + Send + Sync {
+    /// Stores `data` under `key`, replacing any blob already there.
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> anyhow::Result<()>;
+
+    /// Deletes every blob whose key starts with `prefix/`.
+    async fn delete_prefix(&self, prefix: &str) -> anyhow::Result<()>;
+
+    /// URL the blob under `key` is served at.
+    fn url(&self, key: &str) -> String;
+}
+
+/// Builds the blob store selected by `BLOB_DIR` and `BLOB_BASE_URL`.
+pub fn from_config(config: &Config) -> Arc<dyn BlobStore> {
+    Arc::new(LocalBlobStore::new(
+        config.blob_dir.clone(),
+        config.blob_base_url.clone(),
+    ))
+}
+
+/// Whether `key` is relative and made of `/`-separated segments without `.`
+/// or `..`, so it can't escape wherever blobs are kept.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && !segment.contains('\\')
+                && !segment.contains('\0')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_keys() {
+        assert!(is_valid_key("avatars/3/a.jpg"));
+        assert!(is_valid_key("a.jpg"));
+
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("/etc/passwd"));
+        assert!(!is_valid_key("avatars/../../etc/passwd"));
+        assert!(!is_valid_key("avatars/./a.jpg"));
+        assert!(!is_valid_key("avatars//a.jpg"));
+        assert!(!is_valid_key("avatars\\..\\a.jpg"));
+    }
+}
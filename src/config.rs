@@ -1,48 +1,418 @@
-use std::env;
+use std::{env, path::PathBuf, str::FromStr};
+
+use reqwest::Url;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub db_url: String,
-    pub jwt_secret: String,
+    /// Directory of the `<kid>.pem` keys access tokens are signed and verified
+    /// with, see `jwt::JwtKeys`.
+    pub jwt_keys_dir: PathBuf,
+    /// Key new access tokens are signed with.
+    pub jwt_active_kid: String,
+    /// `iss` of access tokens, which must match to be accepted.
+    pub jwt_issuer: String,
+    /// `aud` of access tokens, which must match to be accepted.
+    pub jwt_audience: String,
+    /// Clock skew, in seconds, tolerated when checking `nbf` and `exp`.
+    pub jwt_leeway: u64,
+    pub access_token_ttl: u64,
+    pub refresh_token_ttl: u64,
+    pub password_reset_ttl: u64,
+    pub verification_code_ttl: u64,
+    pub verification_max_attempts: i32,
+    pub verification_resend_cooldown: u64,
+    /// Page of the client app that completes a password reset. When set,
+    /// reset emails link to it with the token in a `token` query parameter.
+    pub password_reset_url: Option<Url>,
+    /// Lifetime of passwordless login codes and links.
+    pub email_login_ttl: u64,
+    /// Wrong codes after which a passwordless login has to be requested again.
+    pub email_login_max_attempts: i32,
+    /// Seconds before another passwordless login can be emailed to a user.
+    pub email_login_resend_cooldown: u64,
+    /// Page of the client app that completes a passwordless login. When set,
+    /// login emails link to it with the token in a `token` query parameter.
+    pub email_login_url: Option<Url>,
+    /// Name authenticator apps show next to the account of a TOTP secret.
+    pub totp_issuer: String,
+    /// Lifetime of the challenge token of a login awaiting a second factor.
+    pub login_challenge_ttl: u64,
+    pub login_challenge_max_attempts: i32,
+    /// OpenID Connect providers users can log in with.
+    pub oidc_providers: Vec<OidcProvider>,
+    /// Time a user has to complete a login at an OpenID Connect provider.
+    pub oidc_login_ttl: u64,
+    /// Whether the service runs behind a reverse proxy, whose
+    /// `X-Forwarded-For` header then gives the IP address of clients.
+    pub trust_proxy: bool,
+
+    pub rate_limit_storage: RateLimitStorage,
+    pub rate_limits: RateLimits,
+    /// Failed logins of an email within `login_lockout_duration` after which
+    /// its logins are refused for `login_lockout_duration`.
+    pub login_lockout_threshold: i32,
+    pub login_lockout_duration: u64,
+
+    /// Time users have to wait between changes of their handle.
+    pub handle_change_cooldown: u64,
+    /// Time a previous handle keeps redirecting to the new one, during which
+    /// nobody else can take it.
+    pub handle_redirect_period: u64,
+
+    /// Directory of the local blob store.
+    pub blob_dir: PathBuf,
+    /// URL the blobs are served at, `/blobs` when the app serves them itself.
+    pub blob_base_url: String,
+    /// Largest profile image upload in bytes.
+    pub max_image_upload: usize,
 
-    pub mail_username: String,
-    pub mail_password: String,
-    pub mail_host: String,
-    pub mail_port: u16,
+    pub mail_transport: MailTransport,
     pub mail_email: String,
     pub mail_author: String,
-    pub mail_tls: bool,
+    /// Directory of the email templates, with a subdirectory per locale.
+    pub templates_dir: PathBuf,
+    /// Locale of emails to users without one, or whose locale has no templates.
+    pub default_locale: String,
+
+    pub outbox_poll_interval: u64,
+    pub outbox_max_attempts: i32,
+    pub outbox_retry_base: u64,
+
+    /// Bearer token for the operator endpoints under `/ops`. They are disabled
+    /// when unset.
+    pub ops_token: Option<String>,
+}
+
+/// How outgoing email is delivered, selected by `MAIL_TRANSPORT`.
+#[derive(Debug, Clone)]
+pub enum MailTransport {
+    /// `smtp` (default): send through an SMTP relay.
+    Smtp(SmtpConfig),
+    /// `file`: write `.eml` files into `MAIL_DIR`.
+    File(PathBuf),
+    /// `log`: log emails without sending them.
+    Log,
+}
+
+/// Where rate limits and lockouts are kept, selected by `RATE_LIMIT_STORAGE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStorage {
+    /// `memory` (default): in the process, so each instance limits on its own.
+    Memory,
+    /// `postgres`: in the database, shared by every instance.
+    Postgres,
+}
+
+/// A token bucket holding up to `requests`, refilled at `requests` per
+/// `seconds`. Written `<requests>/<seconds>`, such as `10/60`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub seconds: u64,
+}
+
+/// The limits of the routes open to brute force and abuse, each applied per
+/// client IP and per target email. Set by the `RATE_LIMIT_<ROUTE>` variables.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// `/auth/login`, `/auth/login/2fa`, and the routes that ask for the
+    /// password again.
+    pub login: RateLimit,
+    pub register: RateLimit,
+    /// `/auth/verify` and `/auth/verify/resend`.
+    pub verify: RateLimit,
+    pub password_reset: RateLimit,
+    /// `/auth/login/email` and `/auth/login/email/confirm`.
+    pub email_login: RateLimit,
+}
+
+/// An OpenID Connect provider, named in `OIDC_PROVIDERS`, a comma-separated
+/// list, and configured by the `OIDC_<NAME>_*` variables.
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    /// Name of the provider in the `/auth/oidc/{provider}` paths.
+    pub name: String,
+    /// Issuer identifier, where `/.well-known/openid-configuration` is found.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where the provider sends users back to. It must be registered with the
+    /// provider, and pass the `code` and `state` query parameters on to
+    /// `/auth/oidc/{provider}/callback`.
+    pub redirect_url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub username: String,
+    pub password: String,
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
 }
 
 impl Config {
     pub fn from_env() -> Config {
         let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+        let jwt_keys_dir = env::var("JWT_KEYS_DIR")
+            .unwrap_or_else(|_| "keys".to_owned())
+            .into();
+        let jwt_active_kid = env::var("JWT_ACTIVE_KID").expect("JWT_ACTIVE_KID must be set");
+        let jwt_issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| "gossip".to_owned());
+        let jwt_audience = env::var("JWT_AUDIENCE").unwrap_or_else(|_| "gossip".to_owned());
+        let jwt_leeway = env::var("JWT_LEEWAY")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(0))
+            .expect("JWT_LEEWAY must be a number of seconds");
+        let access_token_ttl = env::var("ACCESS_TOKEN_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 15))
+            .expect("ACCESS_TOKEN_TTL must be a number of seconds");
+        let refresh_token_ttl = env::var("REFRESH_TOKEN_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 60 * 24 * 30))
+            .expect("REFRESH_TOKEN_TTL must be a number of seconds");
+        let password_reset_ttl = env::var("PASSWORD_RESET_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 60))
+            .expect("PASSWORD_RESET_TTL must be a number of seconds");
+        let password_reset_url = env::var("PASSWORD_RESET_URL")
+            .ok()
+            .map(|v| Url::parse(&v).expect("PASSWORD_RESET_URL must be an absolute URL"));
+        let email_login_ttl = env::var("EMAIL_LOGIN_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 10))
+            .expect("EMAIL_LOGIN_TTL must be a number of seconds");
+        let email_login_max_attempts = env::var("EMAIL_LOGIN_MAX_ATTEMPTS")
+            .map(|v| v.parse::<i32>())
+            .unwrap_or(Ok(5))
+            .expect("EMAIL_LOGIN_MAX_ATTEMPTS must be a number");
+        let email_login_resend_cooldown = env::var("EMAIL_LOGIN_RESEND_COOLDOWN")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60))
+            .expect("EMAIL_LOGIN_RESEND_COOLDOWN must be a number of seconds");
+        let email_login_url = env::var("EMAIL_LOGIN_URL")
+            .ok()
+            .map(|v| Url::parse(&v).expect("EMAIL_LOGIN_URL must be an absolute URL"));
+        let verification_code_ttl = env::var("VERIFICATION_CODE_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 15))
+            .expect("VERIFICATION_CODE_TTL must be a number of seconds");
+        let verification_max_attempts = env::var("VERIFICATION_MAX_ATTEMPTS")
+            .map(|v| v.parse::<i32>())
+            .unwrap_or(Ok(5))
+            .expect("VERIFICATION_MAX_ATTEMPTS must be a number");
+        let verification_resend_cooldown = env::var("VERIFICATION_RESEND_COOLDOWN")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60))
+            .expect("VERIFICATION_RESEND_COOLDOWN must be a number of seconds");
+        let totp_issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| "Gossip".to_owned());
+        let login_challenge_ttl = env::var("LOGIN_CHALLENGE_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 5))
+            .expect("LOGIN_CHALLENGE_TTL must be a number of seconds");
+        let login_challenge_max_attempts = env::var("LOGIN_CHALLENGE_MAX_ATTEMPTS")
+            .map(|v| v.parse::<i32>())
+            .unwrap_or(Ok(5))
+            .expect("LOGIN_CHALLENGE_MAX_ATTEMPTS must be a number");
+        let oidc_providers = env::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(OidcProvider::from_env)
+            .collect();
+        let oidc_login_ttl = env::var("OIDC_LOGIN_TTL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 10))
+            .expect("OIDC_LOGIN_TTL must be a number of seconds");
+        let trust_proxy = env::var("TRUST_PROXY")
+            .map(|v| v.parse::<bool>())
+            .unwrap_or(Ok(false))
+            .expect("TRUST_PROXY must be true or false");
 
-        let mail_username = env::var("MAIL_USERNAME").expect("MAIL_USERNAME must be set");
-        let mail_password = env::var("MAIL_PASSWORD").expect("MAIL_PASSWORD must be set");
-        let mail_host = env::var("MAIL_HOST").expect("MAIL_HOST must be set");
-        let mail_port = env::var("MAIL_PORT")
-            .expect("MAIL_PORT must be set")
-            .parse::<u16>()
-            .expect("MAIL_PORT must be a number");
+        let rate_limit_storage = match env::var("RATE_LIMIT_STORAGE").as_deref() {
+            Ok("memory") | Err(_) => RateLimitStorage::Memory,
+            Ok("postgres") => RateLimitStorage::Postgres,
+            Ok(_) => panic!("RATE_LIMIT_STORAGE must be one of memory or postgres"),
+        };
+        let rate_limits = RateLimits {
+            login: RateLimit::from_env("RATE_LIMIT_LOGIN", 10, 60),
+            register: RateLimit::from_env("RATE_LIMIT_REGISTER", 5, 60 * 60),
+            verify: RateLimit::from_env("RATE_LIMIT_VERIFY", 10, 60 * 10),
+            password_reset: RateLimit::from_env("RATE_LIMIT_PASSWORD_RESET", 5, 60 * 60),
+            email_login: RateLimit::from_env("RATE_LIMIT_EMAIL_LOGIN", 10, 60 * 10),
+        };
+        let login_lockout_threshold = env::var("LOGIN_LOCKOUT_THRESHOLD")
+            .map(|v| v.parse::<i32>())
+            .unwrap_or(Ok(10))
+            .expect("LOGIN_LOCKOUT_THRESHOLD must be a number");
+        let login_lockout_duration = env::var("LOGIN_LOCKOUT_DURATION")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 15))
+            .expect("LOGIN_LOCKOUT_DURATION must be a number of seconds");
+        let handle_change_cooldown = env::var("HANDLE_CHANGE_COOLDOWN")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 60 * 24 * 30))
+            .expect("HANDLE_CHANGE_COOLDOWN must be a number of seconds");
+        let handle_redirect_period = env::var("HANDLE_REDIRECT_PERIOD")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(60 * 60 * 24 * 14))
+            .expect("HANDLE_REDIRECT_PERIOD must be a number of seconds");
+
+        let blob_dir = env::var("BLOB_DIR")
+            .unwrap_or_else(|_| "blobs".to_owned())
+            .into();
+        let blob_base_url = env::var("BLOB_BASE_URL").unwrap_or_else(|_| "/blobs".to_owned());
+        let max_image_upload = env::var("MAX_IMAGE_UPLOAD")
+            .map(|v| v.parse::<usize>())
+            .unwrap_or(Ok(10 * 1024 * 1024))
+            .expect("MAX_IMAGE_UPLOAD must be a number of bytes");
+
+        let mail_transport = match env::var("MAIL_TRANSPORT").as_deref() {
+            Ok("smtp") | Err(_) => MailTransport::Smtp(SmtpConfig::from_env()),
+            Ok("file") => {
+                MailTransport::File(env::var("MAIL_DIR").expect("MAIL_DIR must be set").into())
+            }
+            Ok("log") => MailTransport::Log,
+            Ok(_) => panic!("MAIL_TRANSPORT must be one of smtp, file or log"),
+        };
         let mail_email = env::var("MAIL_EMAIL").expect("MAIL_EMAIL must be set");
         let mail_author = env::var("MAIL_AUTHOR").expect("MAIL_AUTHOR must be set");
-        let mail_tls = env::var("MAIL_TLS")
-            .map(|v| v.parse::<bool>())
-            .unwrap_or(Ok(false))
-            .expect("MAIL_TLS must be a boolean");
+        let templates_dir = env::var("TEMPLATES_DIR")
+            .unwrap_or_else(|_| "templates".to_owned())
+            .into();
+        let default_locale = env::var("DEFAULT_LOCALE").unwrap_or_else(|_| "en".to_owned());
+
+        let outbox_poll_interval = env::var("OUTBOX_POLL_INTERVAL")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(2))
+            .expect("OUTBOX_POLL_INTERVAL must be a number of seconds");
+        let outbox_max_attempts = env::var("OUTBOX_MAX_ATTEMPTS")
+            .map(|v| v.parse::<i32>())
+            .unwrap_or(Ok(8))
+            .expect("OUTBOX_MAX_ATTEMPTS must be a number");
+        let outbox_retry_base = env::var("OUTBOX_RETRY_BASE")
+            .map(|v| v.parse::<u64>())
+            .unwrap_or(Ok(30))
+            .expect("OUTBOX_RETRY_BASE must be a number of seconds");
+
+        let ops_token = env::var("OPS_TOKEN").ok();
 
         Config {
             db_url,
-            jwt_secret,
-            mail_username,
-            mail_password,
-            mail_host,
-            mail_port,
+            jwt_keys_dir,
+            jwt_active_kid,
+            jwt_issuer,
+            jwt_audience,
+            jwt_leeway,
+            access_token_ttl,
+            refresh_token_ttl,
+            password_reset_ttl,
+            password_reset_url,
+            email_login_ttl,
+            email_login_max_attempts,
+            email_login_resend_cooldown,
+            email_login_url,
+            verification_code_ttl,
+            verification_max_attempts,
+            verification_resend_cooldown,
+            totp_issuer,
+            login_challenge_ttl,
+            login_challenge_max_attempts,
+            oidc_providers,
+            oidc_login_ttl,
+            trust_proxy,
+            rate_limit_storage,
+            rate_limits,
+            login_lockout_threshold,
+            login_lockout_duration,
+            handle_change_cooldown,
+            handle_redirect_period,
+            blob_dir,
+            blob_base_url,
+            max_image_upload,
+            mail_transport,
             mail_email,
             mail_author,
-            mail_tls,
+            templates_dir,
+            default_locale,
+            outbox_poll_interval,
+            outbox_max_attempts,
+            outbox_retry_base,
+            ops_token,
+        }
+    }
+}
+
+impl RateLimit {
+    fn from_env(name: &str, requests: u32, seconds: u64) -> RateLimit {
+        env::var(name)
+            .map(|v| v.parse::<RateLimit>())
+            .unwrap_or(Ok(RateLimit { requests, seconds }))
+            .unwrap_or_else(|_| panic!("{name} must be <requests>/<seconds>, both above 0"))
+    }
+
+    /// Requests added to the bucket per second.
+    pub fn refill_rate(&self) -> f64 {
+        self.requests as f64 / self.seconds as f64
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RateLimit, ()> {
+        let (requests, seconds) = s.split_once('/').ok_or(())?;
+        let requests = requests.trim().parse::<u32>().map_err(|_| ())?;
+        let seconds = seconds.trim().parse::<u64>().map_err(|_| ())?;
+
+        if requests == 0 || seconds == 0 {
+            return Err(());
+        }
+
+        Ok(RateLimit { requests, seconds })
+    }
+}
+
+impl SmtpConfig {
+    fn from_env() -> SmtpConfig {
+        let username = env::var("MAIL_USERNAME").expect("MAIL_USERNAME must be set");
+        let password = env::var("MAIL_PASSWORD").expect("MAIL_PASSWORD must be set");
+        let host = env::var("MAIL_HOST").expect("MAIL_HOST must be set");
+        let port = env::var("MAIL_PORT")
+            .expect("MAIL_PORT must be set")
+            .parse::<u16>()
+            .expect("MAIL_PORT must be a number");
+        let tls = env::var("MAIL_TLS")
+            .map(|v| v.parse::<bool>())
+            .unwrap_or(Ok(false))
+            .expect("MAIL_TLS must be a boolean");
+
+        SmtpConfig {
+            username,
+            password,
+            host,
+            port,
+            tls,
+        }
+    }
+}
+
+impl OidcProvider {
+    fn from_env(name: &str) -> OidcProvider {
+        let var = |suffix: &str| {
+            let key = format!("OIDC_{}_{suffix}", name.to_uppercase());
+            env::var(&key).unwrap_or_else(|_| panic!("{key} must be set"))
+        };
+
+        OidcProvider {
+            name: name.to_lowercase(),
+            issuer: var("ISSUER"),
+            client_id: var("CLIENT_ID"),
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: var("REDIRECT_URL"),
         }
     }
 }
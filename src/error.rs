@@ -0,0 +1,304 @@
+use anyhow::anyhow;
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Error of any handler, extractor or repository. Rendered as an RFC 7807
+/// problem details document.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request is malformed, with the reason shown to the client.
+    InvalidRequest(String),
+    /// The body is not `application/json`.
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    /// The access token is missing, invalid or its session was revoked.
+    Unauthorized,
+    /// A personal access token was used for something outside its scopes.
+    InsufficientScope,
+    InvalidCredentials,
+    EmailNotVerified,
+    IncorrectPassword,
+    NotFound,
+    EmailTaken,
+    /// Someone else has the handle, or had it until recently.
+    HandleTaken,
+    InvalidCode,
+    CodeExpired,
+    TooManyAttempts,
+    InvalidRefreshToken,
+    InvalidResetToken,
+    InvalidChallenge,
+    TwoFactorEnabled,
+    /// A login at an OpenID Connect provider failed, details are only logged.
+    OidcLoginFailed,
+    /// The action was taken too recently, it can be retried after
+    /// `retry_after` seconds.
+    RetryLater {
+        retry_after: i64,
+    },
+    /// Anything the client can't do anything about. Logged, but never shown.
+    Internal(anyhow::Error),
+}
+
+/// Stable, machine-readable identifier of an error, found in the `code` field
+/// of problem details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    Unauthorized,
+    InsufficientScope,
+    InvalidCredentials,
+    EmailNotVerified,
+    IncorrectPassword,
+    NotFound,
+    EmailTaken,
+    HandleTaken,
+    InvalidCode,
+    CodeExpired,
+    TooManyAttempts,
+    InvalidRefreshToken,
+    InvalidResetToken,
+    InvalidChallenge,
+    TwoFactorEnabled,
+    OidcLoginFailed,
+    RetryLater,
+    Internal,
+}
+
+/// RFC 7807 problem details, the body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// Always `about:blank`, errors are told apart by `code`.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// Reason phrase of the status code.
+    pub title: String,
+    pub status: u16,
+    /// Human-readable explanation, not meant to be parsed.
+    pub detail: String,
+    pub code: ErrorCode,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::InvalidRequest(_) | AppError::InvalidCode | AppError::InvalidResetToken => {
+                StatusCode::BAD_REQUEST
+            }
+            AppError::Unauthorized
+            | AppError::InvalidCredentials
+            | AppError::InvalidRefreshToken
+            | AppError::InvalidChallenge
+            | AppError::OidcLoginFailed => StatusCode::UNAUTHORIZED,
+            AppError::InsufficientScope
+            | AppError::EmailNotVerified
+            | AppError::IncorrectPassword => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::EmailTaken | AppError::HandleTaken | AppError::TwoFactorEnabled => {
+                StatusCode::CONFLICT
+            }
+            AppError::CodeExpired => StatusCode::GONE,
+            AppError::TooManyAttempts | AppError::RetryLater { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            AppError::UnsupportedMediaType => ErrorCode::UnsupportedMediaType,
+            AppError::PayloadTooLarge => ErrorCode::PayloadTooLarge,
+            AppError::Unauthorized => ErrorCode::Unauthorized,
+            AppError::InsufficientScope => ErrorCode::InsufficientScope,
+            AppError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            AppError::EmailNotVerified => ErrorCode::EmailNotVerified,
+            AppError::IncorrectPassword => ErrorCode::IncorrectPassword,
+            AppError::NotFound => ErrorCode::NotFound,
+            AppError::EmailTaken => ErrorCode::EmailTaken,
+            AppError::HandleTaken => ErrorCode::HandleTaken,
+            AppError::InvalidCode => ErrorCode::InvalidCode,
+            AppError::CodeExpired => ErrorCode::CodeExpired,
+            AppError::TooManyAttempts => ErrorCode::TooManyAttempts,
+            AppError::InvalidRefreshToken => ErrorCode::InvalidRefreshToken,
+            AppError::InvalidResetToken => ErrorCode::InvalidResetToken,
+            AppError::InvalidChallenge => ErrorCode::InvalidChallenge,
+            AppError::TwoFactorEnabled => ErrorCode::TwoFactorEnabled,
+            AppError::OidcLoginFailed => ErrorCode::OidcLoginFailed,
+            AppError::RetryLater { .. } => ErrorCode::RetryLater,
+            AppError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            AppError::InvalidRequest(reason) => reason.clone(),
+            AppError::UnsupportedMediaType => {
+                "Expected a request with `Content-Type: application/json`.".to_owned()
+            }
+            AppError::PayloadTooLarge => "The request body is too large.".to_owned(),
+            AppError::Unauthorized => "Missing or invalid access token.".to_owned(),
+            AppError::InsufficientScope => "The access token doesn't allow this action.".to_owned(),
+            AppError::InvalidCredentials => "Invalid email or password.".to_owned(),
+            AppError::EmailNotVerified => "The email address is not verified yet.".to_owned(),
+            AppError::IncorrectPassword => "The current password is incorrect.".to_owned(),
+            AppError::NotFound => "The resource does not exist.".to_owned(),
+            AppError::EmailTaken => "The email address is already taken.".to_owned(),
+            AppError::HandleTaken => "The handle is already taken.".to_owned(),
+            AppError::InvalidCode => "Invalid verification code.".to_owned(),
+            AppError::CodeExpired => "The verification code expired, request a new one.".to_owned(),
+            AppError::TooManyAttempts => "Too many attempts, request a new code.".to_owned(),
+            AppError::InvalidRefreshToken => "Invalid, expired or reused refresh token.".to_owned(),
+            AppError::InvalidResetToken => "Invalid or expired reset token.".to_owned(),
+            AppError::InvalidChallenge => {
+                "Invalid or expired login challenge, log in again.".to_owned()
+            }
+            AppError::TwoFactorEnabled => {
+                "Two-factor authentication is already enabled.".to_owned()
+            }
+            AppError::OidcLoginFailed => "The login with the identity provider failed.".to_owned(),
+            AppError::RetryLater { retry_after } => {
+                format!("Retry after {retry_after} seconds.")
+            }
+            AppError::Internal(_) => "Internal server error.".to_owned(),
+        }
+    }
+
+    pub fn problem_details(&self) -> ProblemDetails {
+        let status = self.status();
+
+        ProblemDetails {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or_default().to_owned(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: self.code(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if let AppError::Internal(e) = &self {
+            tracing::error!("Internal error: {:#}", e);
+        }
+
+        let problem = self.problem_details();
+        let content_type = [(header::CONTENT_TYPE, "application/problem+json")];
+        let mut response = (self.status(), content_type, Json(problem)).into_response();
+
+        if let AppError::RetryLater { retry_after } = self {
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                retry_after.max(1).to_string().parse().unwrap(),
+            );
+        }
+
+        response
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> AppError {
+        AppError::Internal(e.into())
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> AppError {
+        AppError::Internal(e)
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> AppError {
+        match rejection.status() {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType,
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+            status if status.is_server_error() => {
+                AppError::Internal(anyhow!(rejection.body_text()))
+            }
+            _ => AppError::InvalidRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> AppError {
+        // Missing parameters are a mistake in the router, not the request.
+        if rejection.status().is_server_error() {
+            AppError::Internal(anyhow!(rejection.body_text()))
+        } else {
+            AppError::InvalidRequest(rejection.body_text())
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> AppError {
+        AppError::InvalidRequest(rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_renders_problem_details() {
+        let response = AppError::EmailTaken.into_response();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Conflict",
+                "status": 409,
+                "detail": "The email address is already taken.",
+                "code": "email_taken",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hides_internal_errors() {
+        let response = AppError::Internal(anyhow::anyhow!("connection refused")).into_response();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(!body.contains("connection refused"));
+        assert!(body.contains(r#""code":"internal""#));
+    }
+
+    #[test]
+    fn test_retry_later_sets_retry_after() {
+        let response = AppError::RetryLater { retry_after: 42 }.into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "42");
+    }
+}
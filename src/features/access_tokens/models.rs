@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::features::auth::models::Scope;
+
+/// Personal access tokens start with it, which tells them apart from JWTs.
+pub const TOKEN_PREFIX: &str = "gsp_";
+
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+pub struct CreateAccessTokenRequest {
+    /// What the token is for, like the name of the script using it.
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Lifetime of the token in seconds. It never expires when omitted.
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct AccessTokenRow {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: OffsetDateTime,
+    pub expires_at: Option<OffsetDateTime>,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub expires_at: Option<OffsetDateTime>,
+    /// Last use of the token, updated every few minutes at most.
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+impl From<AccessTokenRow> for PersonalAccessToken {
+    fn from(row: AccessTokenRow) -> PersonalAccessToken {
+        PersonalAccessToken {
+            id: row.id,
+            name: row.name,
+            scopes: row
+                .scopes
+                .iter()
+                .filter_map(|scope| Scope::parse(scope))
+                .collect(),
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedAccessToken {
+    /// The token, to be sent as a Bearer token. It is only shown now.
+    pub token: String,
+    #[serde(flatten)]
+    pub access_token: PersonalAccessToken,
+}
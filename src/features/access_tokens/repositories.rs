@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use axum::{async_trait, Extension};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{db::Db, error::AppError};
+
+use super::models::{AccessTokenRow, PersonalAccessToken};
+
+pub type AccessTokenRepoExt = Extension<Arc<AccessTokenRepo>>;
+
+pub struct AccessTokenRepo {
+    pub db: Db,
+}
+
+#[async_trait]
+pub trait AccessTokenRepoImpl {
+    async fn create_token(
+        &self,
+        user_id: i32,
+        name: &str,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<PersonalAccessToken, AppError>;
+
+    /// The tokens of the user that aren't revoked, newest first.
+    async fn list_tokens(&self, user_id: i32) -> Result<Vec<PersonalAccessToken>, AppError>;
+
+    /// Revokes a token if it belongs to the user. Returns `false` if it
+    /// doesn't, or is already revoked.
+    async fn revoke_token(&self, user_id: i32, token_id: Uuid) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+impl AccessTokenRepoImpl for AccessTokenRepo {
+    async fn create_token(
+        &self,
+        user_id: i32,
+        name: &str,
+        token_hash: &str,
+        scopes: &[String],
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<PersonalAccessToken, AppError> {
+        let token = sqlx::query_file_as!(
+            AccessTokenRow,
+            "queries/access_tokens/create_token.sql",
+            user_id,
+            name,
+            token_hash,
+            scopes,
+            expires_at
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(token.into())
+    }
+
+    async fn list_tokens(&self, user_id: i32) -> Result<Vec<PersonalAccessToken>, AppError> {
+        let tokens = sqlx::query_file_as!(
+            AccessTokenRow,
+            "queries/access_tokens/list_tokens.sql",
+            user_id
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(tokens.into_iter().map(Into::into).collect())
+    }
+
+    async fn revoke_token(&self, user_id: i32, token_id: Uuid) -> Result<bool, AppError> {
+        let revoked =
+            sqlx::query_file_scalar!("queries/access_tokens/revoke_token.sql", token_id, user_id)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(revoked.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::features::auth::{
+        models::Scope,
+        repositories::{AuthRepo, AuthRepoImpl},
+    };
+
+    async fn create_user(pool: &PgPool, email: &str) -> i32 {
+        let repo = AuthRepo { db: pool.clone() };
+
+        repo.create_user(email, "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn token_user(pool: &PgPool, token_hash: &str) -> Option<i32> {
+        sqlx::query_file!("queries/access_tokens/get_principal.sql", token_hash)
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+            .map(|principal| principal.user_id)
+    }
+
+    #[sqlx::test]
+    async fn test_create_and_revoke_token(pool: PgPool) {
+        let repo = AccessTokenRepo { db: pool.clone() };
+        let user_id = create_user(&pool, "a.b@c.com").await;
+        let other_id = create_user(&pool, "d.e@f.com").await;
+        let scopes = vec!["profile:read".to_owned(), "future:scope".to_owned()];
+
+        let token = repo
+            .create_token(user_id, "backup script", "hash", &scopes, None)
+            .await
+            .unwrap();
+
+        // Unknown scopes are dropped.
+        assert_eq!(token.name, "backup script");
+        assert_eq!(token.scopes, vec![Scope::ProfileRead]);
+        assert!(token.expires_at.is_none());
+        assert_eq!(token_user(&pool, "hash").await, Some(user_id));
+
+        let tokens = repo.list_tokens(user_id).await.unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].id, token.id);
+        assert!(repo.list_tokens(other_id).await.unwrap().is_empty());
+
+        // Tokens of other users can't be revoked.
+        assert!(!repo.revoke_token(other_id, token.id).await.unwrap());
+        assert_eq!(token_user(&pool, "hash").await, Some(user_id));
+
+        assert!(repo.revoke_token(user_id, token.id).await.unwrap());
+        assert!(!repo.revoke_token(user_id, token.id).await.unwrap());
+        assert_eq!(token_user(&pool, "hash").await, None);
+        assert!(repo.list_tokens(user_id).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_expired_token_is_rejected(pool: PgPool) {
+        let repo = AccessTokenRepo { db: pool.clone() };
+        let user_id = create_user(&pool, "a.b@c.com").await;
+        let now = OffsetDateTime::now_utc();
+        let scopes = vec!["profile:read".to_owned()];
+
+        repo.create_token(user_id, "old", "old", &scopes, Some(now))
+            .await
+            .unwrap();
+        repo.create_token(
+            user_id,
+            "new",
+            "new",
+            &scopes,
+            Some(now + time::Duration::hours(1)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(token_user(&pool, "old").await, None);
+        assert_eq!(token_user(&pool, "new").await, Some(user_id));
+
+        // Expired tokens are still listed, until revoked.
+        assert_eq!(repo.list_tokens(user_id).await.unwrap().len(), 2);
+    }
+
+    #[sqlx::test]
+    async fn test_password_change_and_reset_revoke_tokens(pool: PgPool) {
+        let repo = AccessTokenRepo { db: pool.clone() };
+        let auth_repo = AuthRepo { db: pool.clone() };
+        let user_id = create_user(&pool, "a.b@c.com").await;
+        let other_id = create_user(&pool, "d.e@f.com").await;
+        let scopes = vec!["profile:read".to_owned()];
+
+        repo.create_token(user_id, "bot", "first", &scopes, None)
+            .await
+            .unwrap();
+        repo.create_token(other_id, "bot", "other", &scopes, None)
+            .await
+            .unwrap();
+
+        auth_repo
+            .change_password(user_id, "def", &[])
+            .await
+            .unwrap();
+        assert_eq!(token_user(&pool, "first").await, None);
+        assert_eq!(token_user(&pool, "other").await, Some(other_id));
+
+        repo.create_token(user_id, "bot", "second", &scopes, None)
+            .await
+            .unwrap();
+        auth_repo
+            .create_password_reset(
+                user_id,
+                "reset",
+                OffsetDateTime::now_utc() + time::Duration::hours(1),
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            auth_repo.reset_password("reset", "ghi").await.unwrap(),
+            Some(user_id)
+        );
+        assert_eq!(token_user(&pool, "second").await, None);
+        assert_eq!(token_user(&pool, "other").await, Some(other_id));
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use axum::{
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Router,
+};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path},
+    features::auth::models::Principal,
+    state::AppState,
+    token,
+};
+
+use super::{
+    models::{CreateAccessTokenRequest, CreatedAccessToken, PersonalAccessToken, TOKEN_PREFIX},
+    repositories::{AccessTokenRepo, AccessTokenRepoExt, AccessTokenRepoImpl},
+};
+
+/// Longest name of a token, in characters.
+const MAX_NAME_LEN: usize = 100;
+
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_tokens).post(create_token))
+        .route("/:id", delete(revoke_token))
+        .layer(Extension(Arc::new(AccessTokenRepo {
+            db: state.db.clone(),
+        })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/tokens",
+    responses(
+        (status = 201, body = CreatedAccessToken),
+        (status = 400, body = ProblemDetails, description = "Invalid name, scopes or lifetime."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+    ),
+    request_body = CreateAccessTokenRequest,
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn create_token(
+    Extension(repo): AccessTokenRepoExt,
+    principal: Principal,
+    Json(body): Json<CreateAccessTokenRequest>,
+) -> Result<(StatusCode, Json<CreatedAccessToken>), AppError> {
+    // A leaked token must not be able to mint more.
+    principal.session_id()?;
+
+    let name = body.name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "Token names must be 1 to {MAX_NAME_LEN} characters long."
+        )));
+    }
+
+    let mut scopes = body
+        .scopes
+        .iter()
+        .map(|scope| scope.as_str().to_owned())
+        .collect::<Vec<_>>();
+    scopes.sort();
+    scopes.dedup();
+
+    if scopes.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "A token needs at least one scope.".to_owned(),
+        ));
+    }
+
+    let expires_at =
+        match body.expires_in {
+            None => None,
+            Some(expires_in) => {
+                let expires_at = i64::try_from(expires_in)
+                    .ok()
+                    .filter(|expires_in| *expires_in > 0)
+                    .and_then(|expires_in| {
+                        OffsetDateTime::now_utc().checked_add(Duration::seconds(expires_in))
+                    });
+
+                Some(expires_at.ok_or_else(|| {
+                    AppError::InvalidRequest("Invalid token lifetime.".to_owned())
+                })?)
+            }
+        };
+
+    let token = format!("{TOKEN_PREFIX}{}", token::generate());
+
+    let access_token = repo
+        .create_token(
+            principal.user_id,
+            name,
+            &token::hash(&token),
+            &scopes,
+            expires_at,
+        )
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedAccessToken {
+            token,
+            access_token,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/tokens",
+    responses(
+        (status = 200, body = [PersonalAccessToken], description = "Tokens of the user that aren't revoked, newest first. Expired ones are included."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn list_tokens(
+    Extension(repo): AccessTokenRepoExt,
+    principal: Principal,
+) -> Result<Json<Vec<PersonalAccessToken>>, AppError> {
+    principal.session_id()?;
+
+    let tokens = repo.list_tokens(principal.user_id).await?;
+
+    Ok(Json(tokens))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/tokens/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Token to revoke."),
+    ),
+    responses(
+        (status = 204, description = "Token revoked."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 404, body = ProblemDetails, description = "No such token of the user."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn revoke_token(
+    Extension(repo): AccessTokenRepoExt,
+    principal: Principal,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    principal.session_id()?;
+
+    if !repo.revoke_token(principal.user_id, token_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,50 +1,291 @@
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::{header, request::Parts, StatusCode},
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use time::{Duration, OffsetDateTime};
+
+use super::models::{AuthUser, ClientInfo, Credential, OptionalAuth, Principal, Scope};
+use crate::{
+    config::Config, error::AppError, features::access_tokens, jwt, state::AppState, token,
 };
 
-use super::models::AuthUser;
-use crate::{jwt, state::AppState};
+/// How stale `last_seen_at` of a session may get before a request updates it,
+/// so that busy clients don't write on every request.
+const LAST_SEEN_INTERVAL: Duration = Duration::minutes(5);
+
+/// Longest header value recorded on a session.
+const MAX_CLIENT_INFO_LEN: usize = 256;
+
+/// The token of the `Authorization: Bearer` header, if any.
+pub fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .map(|auth_value| auth_value.trim().trim_start_matches("Bearer").trim())
+}
+
+/// The IP address of the client: the peer of the connection, or the address
+/// the trusted reverse proxy appended to `X-Forwarded-For`.
+pub fn client_ip(parts: &Parts, config: &Config) -> Option<IpAddr> {
+    if config.trust_proxy {
+        return parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .and_then(|ip| ip.trim().parse().ok());
+    }
+
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// A readable name for the device of a user agent, like "Firefox on Linux".
+fn guess_device_name(user_agent: &str) -> Option<String> {
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ];
+    // iOS and Android agents also mention macOS and Linux, so they go first.
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("iPhone", "iPhone"),
+        ("iPad", "iPad"),
+        ("Android", "Android"),
+        ("Windows", "Windows"),
+        ("Macintosh", "macOS"),
+        ("Linux", "Linux"),
+    ];
+
+    let find = |names: &[(&str, &'static str)]| {
+        names
+            .iter()
+            .find(|(token, _)| user_agent.contains(token))
+            .map(|(_, name)| *name)
+    };
+
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => Some(format!("{browser} on {system}")),
+        (browser, system) => browser.or(system).map(str::to_owned),
+    }
+}
+
+async fn resolve_principal(token: &str, state: &AppState) -> Result<Principal, AppError> {
+    if token.starts_with(access_tokens::models::TOKEN_PREFIX) {
+        return resolve_personal_access_token(token, state).await;
+    }
+
+    let config = &state.config;
+    let claims = jwt::decode(
+        token,
+        &state.jwt_keys,
+        &config.jwt_issuer,
+        &config.jwt_audience,
+        config.jwt_leeway,
+    )
+    .map_err(|_| AppError::Unauthorized)?
+    .claims;
+    let user_id = claims.user_id().ok_or(AppError::Unauthorized)?;
+
+    let session = sqlx::query_file!("queries/auth/get_principal.sql", claims.sid, user_id)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    if session.last_seen_at + LAST_SEEN_INTERVAL <= OffsetDateTime::now_utc() {
+        sqlx::query_file!("queries/auth/touch_session.sql", session.session_id)
+            .execute(&state.db)
+            .await?;
+    }
+
+    Ok(Principal {
+        user_id: session.user_id,
+        is_verified: session.is_verified,
+        credential: Credential::Session(session.session_id),
+    })
+}
+
+async fn resolve_personal_access_token(
+    token: &str,
+    state: &AppState,
+) -> Result<Principal, AppError> {
+    let access_token = sqlx::query_file!(
+        "queries/access_tokens/get_principal.sql",
+        token::hash(token)
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(AppError::Unauthorized)?;
+
+    let stale = access_token
+        .last_used_at
+        .is_none_or(|last_used_at| last_used_at + LAST_SEEN_INTERVAL <= OffsetDateTime::now_utc());
+
+    if stale {
+        sqlx::query_file!(
+            "queries/access_tokens/touch_token.sql",
+            access_token.token_id
+        )
+        .execute(&state.db)
+        .await?;
+    }
+
+    Ok(Principal {
+        user_id: access_token.user_id,
+        is_verified: access_token.is_verified,
+        credential: Credential::PersonalAccessToken {
+            // Scopes this build doesn't know grant nothing.
+            scopes: access_token
+                .scopes
+                .iter()
+                .filter_map(|scope| Scope::parse(scope))
+                .collect(),
+        },
+    })
+}
+
+/// The only place access tokens and personal access tokens are checked. The
+/// principal is cached in the request extensions, so a handler taking several
+/// extractors derived from it still resolves the token once.
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Principal {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, AppError> {
+        if let Some(principal) = parts.extensions.get::<Principal>() {
+            return Ok(principal.clone());
+        }
+
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+        let principal = resolve_principal(token, state).await?;
+
+        parts.extensions.insert(principal.clone());
+
+        Ok(principal)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, AppError> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| value.chars().take(MAX_CLIENT_INFO_LEN).collect::<String>())
+        };
+
+        let user_agent = header(header::USER_AGENT.as_str());
+        let device_name =
+            header("x-device-name").or_else(|| user_agent.as_deref().and_then(guess_device_name));
+
+        Ok(ClientInfo {
+            device_name,
+            user_agent,
+            ip_address: client_ip(parts, &state.config).map(|ip| ip.to_string()),
+        })
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for OptionalAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, AppError> {
+        if bearer_token(parts).is_none() {
+            return Ok(OptionalAuth(None));
+        }
+
+        let principal = Principal::from_request_parts(parts, state).await?;
+
+        Ok(OptionalAuth(Some(principal)))
+    }
+}
 
 #[async_trait]
 impl FromRequestParts<Arc<AppState>> for AuthUser {
-    type Rejection = (StatusCode, &'static str);
+    type Rejection = AppError;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &Arc<AppState>,
-    ) -> Result<Self, (StatusCode, &'static str)> {
-        let token = parts
-            .headers
-            .get(header::AUTHORIZATION)
-            .and_then(|auth_header| auth_header.to_str().ok())
-            .map(|auth_value| {
-                auth_value
-                    .trim()
-                    .trim_start_matches("Bearer")
-                    .trim()
-                    .to_owned()
-            });
+    ) -> Result<Self, AppError> {
+        let principal = Principal::from_request_parts(parts, state).await?;
 
-        let token =
-            token.ok_or_else(|| (StatusCode::UNAUTHORIZED, "No authorization token provided"))?;
+        // Only handlers managing the account itself need it, which personal
+        // access tokens must not do.
+        principal.session_id()?;
 
-        let claims = jwt::decode(&token, state.config.jwt_secret.as_ref())
-            .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid token"))?
-            .claims;
+        let user = sqlx::query_file_as!(
+            AuthUser,
+            "queries/auth/get_user_by_id.sql",
+            principal.user_id
+        )
+        .fetch_optional(&state.db)
+        .await?;
 
-        let user_id = claims.id;
+        user.ok_or(AppError::Unauthorized)
+    }
+}
 
-        let user = sqlx::query_file_as!(AuthUser, "queries/auth/get_user_by_id.sql", user_id)
-            .fetch_optional(&state.db)
-            .await
-            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let user = user.ok_or_else(|| (StatusCode::UNAUTHORIZED, "Invalid token"))?;
+    #[test]
+    fn guesses_device_names() {
+        let cases = [
+            (
+                "Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Gecko/20100101 Firefox/120.0",
+                Some("Firefox on Linux"),
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 \
+                 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1",
+                Some("Safari on iPhone"),
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                 (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36 Edg/119.0.0.0",
+                Some("Edge on Windows"),
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14) AppleWebKit/537.36 \
+                 (KHTML, like Gecko) Chrome/119.0.0.0 Mobile Safari/537.36",
+                Some("Chrome on Android"),
+            ),
+            ("gossip-cli/1.0", None),
+        ];
 
-        Ok(user)
+        for (user_agent, device_name) in cases {
+            assert_eq!(guess_device_name(user_agent).as_deref(), device_name);
+        }
     }
 }
@@ -3,4 +3,4 @@ pub mod models;
 pub mod repositories;
 pub mod routes;
 
-pub use routes::router;
+pub use routes::{router, well_known_router};
@@ -1,5 +1,9 @@
 use sqlx::FromRow;
+use time::OffsetDateTime;
 use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::AppError;
 
 #[derive(Debug, Clone, FromRow)]
 pub struct AuthUser {
@@ -8,12 +12,100 @@ pub struct AuthUser {
     pub email: String,
     pub password_hash: String,
     pub is_verified: bool,
+    pub locale: Option<String>,
+}
+
+/// The authenticated caller, resolved once per request from its access token
+/// and shared by every extractor that needs it through request extensions.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: i32,
+    pub is_verified: bool,
+    pub credential: Credential,
 }
 
-#[derive(Debug, sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+/// What the caller authenticated with.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// An access token, issued for this session.
+    Session(Uuid),
+    /// A personal access token, only good for its scopes.
+    PersonalAccessToken { scopes: Vec<Scope> },
+}
+
+impl Principal {
+    /// The session of the caller. Personal access tokens have none, which
+    /// keeps them away from account and session management.
+    pub fn session_id(&self) -> Result<Uuid, AppError> {
+        match self.credential {
+            Credential::Session(session_id) => Ok(session_id),
+            Credential::PersonalAccessToken { .. } => Err(AppError::InsufficientScope),
+        }
+    }
+
+    /// Fails unless the caller may act within `scope`. Sessions may do
+    /// anything.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), AppError> {
+        match &self.credential {
+            Credential::Session(_) => Ok(()),
+            Credential::PersonalAccessToken { scopes, .. } if scopes.contains(&scope) => Ok(()),
+            Credential::PersonalAccessToken { .. } => Err(AppError::InsufficientScope),
+        }
+    }
+}
+
+/// What a personal access token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize, ToSchema)]
+pub enum Scope {
+    /// Read the profile of the user.
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    /// Edit the profile of the user.
+    #[serde(rename = "profile:write")]
+    ProfileWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::ProfileWrite => "profile:write",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "profile:read" => Some(Scope::ProfileRead),
+            "profile:write" => Some(Scope::ProfileWrite),
+            _ => None,
+        }
+    }
+}
+
+/// The caller if the request carries an access token. Endpoints using it serve
+/// anonymous callers too, but an invalid token is still rejected.
+#[derive(Debug, Clone)]
+pub struct OptionalAuth(pub Option<Principal>);
+
+impl OptionalAuth {
+    /// The caller as a signed-in user, unless anonymous or unverified.
+    pub fn user_id(&self) -> Option<i32> {
+        self.0
+            .as_ref()
+            .filter(|principal| principal.is_verified)
+            .map(|principal| principal.user_id)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
 pub struct PendingEmailVerification {
     pub user_id: i32,
+    pub username: String,
+    pub locale: Option<String>,
     pub code: String,
+    pub created_at: OffsetDateTime,
+    /// Number of codes tried so far.
+    pub attempts: i32,
 }
 
 #[derive(Debug, serde::Deserialize, Clone, ToSchema)]
@@ -24,7 +116,49 @@ pub struct LoginRequest {
 
 #[derive(Debug, serde::Serialize, ToSchema)]
 pub struct LoginResponse {
+    /// Short-lived access token to be sent as a Bearer token.
     pub token: String,
+    /// Opaque token that can be exchanged once for a new token pair.
+    pub refresh_token: String,
+    /// Lifetime of the access token in seconds.
+    pub expires_in: u64,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, FromRow)]
+pub struct RefreshedSession {
+    pub session_id: Uuid,
+    pub user_id: i32,
+}
+
+/// The device a request comes from, recorded on the sessions it starts.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    /// Sent by the app in `X-Device-Name`, or else guessed from `user_agent`.
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: OffsetDateTime,
+    /// Last use of the session, updated every few minutes at most.
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub last_seen_at: OffsetDateTime,
+    /// Whether it is the session of the access token of the request.
+    pub current: bool,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, ToSchema)]
@@ -32,6 +166,9 @@ pub struct RegisterRequest {
     pub email: String,
     pub password: String,
     pub name: String,
+    /// Preferred language of emails as a BCP 47 tag, such as `en` or `es-MX`.
+    /// Defaults to the server's locale.
+    pub locale: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, ToSchema)]
@@ -40,8 +177,139 @@ pub struct VerifyEmailRequest {
     pub code: String,
 }
 
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, FromRow)]
+pub struct EmailLogin {
+    pub user_id: i32,
+    pub created_at: OffsetDateTime,
+    /// Number of codes tried so far.
+    pub attempts: i32,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct EmailLoginRequest {
+    pub email: String,
+}
+
+/// Either the code typed from the email, or the token of its link.
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+#[serde(untagged)]
+pub enum EmailLoginConfirmRequest {
+    Code { email: String, code: String },
+    Link { token: String },
+}
+
+#[derive(Debug, FromRow)]
+pub struct TotpAuthenticator {
+    pub secret: Vec<u8>,
+    /// Whether enrollment was confirmed with a first code, after which logins
+    /// require a second factor.
+    pub is_confirmed: bool,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct TotpEnrollment {
+    /// Base32 secret, for authenticator apps that can't scan `otpauth_uri`.
+    pub secret: String,
+    /// URI to show as a QR code for authenticator apps to scan.
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct ConfirmTotpRequest {
+    /// Current code of the authenticator.
+    pub code: String,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    /// Codes that can each stand in once for an authenticator code. They are
+    /// only shown now.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct DisableTwoFactorRequest {
+    pub password: String,
+    /// Current code of the authenticator, or an unused recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct TwoFactorChallenge {
+    /// Token to send to `/auth/login/2fa` along with a second factor.
+    pub challenge_token: String,
+    /// Lifetime of the challenge token in seconds.
+    pub expires_in: u64,
+}
+
+#[derive(Debug, serde::Deserialize, Clone, ToSchema)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    /// Current code of the authenticator, or an unused recovery code.
+    pub code: String,
+}
+
+/// Claims of an access token, as registered by RFC 7519. Times are in seconds
+/// since the Unix epoch.
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct TokenClaims {
-    pub id: i32,
-    pub exp: usize,
+    /// Id of the user, as a string.
+    pub sub: String,
+    /// Session the token was issued for.
+    pub sid: Uuid,
+    pub iss: String,
+    pub aud: String,
+    pub iat: u64,
+    pub nbf: u64,
+    /// First second the token is expired, `ttl` seconds after `iat`.
+    pub exp: u64,
+    pub jti: Uuid,
+}
+
+impl TokenClaims {
+    pub fn new(
+        user_id: i32,
+        session_id: Uuid,
+        issuer: &str,
+        audience: &str,
+        issued_at: u64,
+        ttl: u64,
+    ) -> TokenClaims {
+        TokenClaims {
+            sub: user_id.to_string(),
+            sid: session_id,
+            iss: issuer.to_owned(),
+            aud: audience.to_owned(),
+            iat: issued_at,
+            nbf: issued_at,
+            exp: issued_at + ttl,
+            jti: Uuid::new_v4(),
+        }
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        self.sub.parse().ok()
+    }
 }
@@ -1,10 +1,19 @@
 use std::sync::Arc;
 
 use axum::async_trait;
+use time::OffsetDateTime;
+use uuid::Uuid;
 
-use crate::{db::Db, features::auth::models::PendingEmailVerification};
+use crate::{
+    db::Db,
+    error::AppError,
+    features::{auth::models::PendingEmailVerification, outbox},
+    mail::Email,
+};
 
-use super::models::AuthUser;
+use super::models::{
+    AuthUser, ClientInfo, EmailLogin, RefreshedSession, Session, TotpAuthenticator,
+};
 
 pub type AuthRepoExt = Arc<AuthRepo>;
 
@@ -12,9 +21,14 @@ pub struct AuthRepo {
     pub db: Db,
 }
 
+/// Methods taking `mail` queue those emails in the outbox, in the same
+/// transaction as their write, and only if the write takes place.
 #[async_trait]
 pub trait AuthRepoImpl {
-    async fn find_user_id_password_by_email(&self, email: &str) -> Option<AuthUser>;
+    async fn find_user_id_password_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<AuthUser>, AppError>;
 
     async fn create_user(
         &self,
@@ -22,22 +36,214 @@ pub trait AuthRepoImpl {
         password_hash: &str,
         name: &str,
         code: &str,
-    ) -> Option<i32>;
+        locale: Option<&str>,
+        mail: &[Email],
+    ) -> Result<Option<i32>, AppError>;
+
+    async fn is_email_taken(&self, email: &str) -> Result<bool, AppError>;
+
+    async fn get_pending_verification(
+        &self,
+        email: &str,
+    ) -> Result<Option<PendingEmailVerification>, AppError>;
+
+    /// Counts an attempt at the user's verification code. Returns the number of
+    /// attempts so far, or `None` if the limit has already been reached.
+    async fn record_verification_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError>;
+
+    /// Replaces the user's verification code, unless the current one was issued
+    /// less than `cooldown_seconds` ago. Returns `false` during the cooldown.
+    async fn regenerate_verification_code(
+        &self,
+        user_id: i32,
+        code: &str,
+        cooldown_seconds: f64,
+        mail: &[Email],
+    ) -> Result<bool, AppError>;
+
+    async fn verify_email(&self, email: &str, mail: &[Email])
+        -> Result<Option<AuthUser>, AppError>;
+
+    /// Starts a new session for the user, issuing its first refresh token.
+    async fn create_session(
+        &self,
+        user_id: i32,
+        client: &ClientInfo,
+        refresh_token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<Uuid, AppError>;
+
+    /// The sessions of the user that are neither revoked nor expired, flagging
+    /// `current_session_id` as current.
+    async fn list_sessions(
+        &self,
+        user_id: i32,
+        current_session_id: Uuid,
+    ) -> Result<Vec<Session>, AppError>;
+
+    /// Exchanges a live refresh token for a new one in the same session.
+    /// Returns `None` if the token is unknown, expired, already used or its
+    /// session has been revoked.
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<Option<RefreshedSession>, AppError>;
+
+    /// Revokes the session of an already rotated refresh token.
+    /// Returns `true` if the token was in fact being reused.
+    async fn revoke_reused_refresh_token(&self, refresh_token_hash: &str)
+        -> Result<bool, AppError>;
+
+    /// Revokes a single session, invalidating its access and refresh tokens.
+    async fn revoke_session(&self, session_id: Uuid) -> Result<(), AppError>;
+
+    /// Revokes every session of the user.
+    async fn revoke_all_sessions(&self, user_id: i32) -> Result<(), AppError>;
+
+    /// Revokes a session if it belongs to the user. Returns `false` if it
+    /// doesn't, or is already revoked.
+    async fn revoke_user_session(&self, user_id: i32, session_id: Uuid) -> Result<bool, AppError>;
+
+    /// Stores a password reset token, replacing any earlier one of the user.
+    async fn create_password_reset(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+        mail: &[Email],
+    ) -> Result<(), AppError>;
+
+    /// Sets a new password if the reset token is live, and revokes every
+    /// session and personal access token of the user. Returns the user, or
+    /// `None` if the token is unknown or expired.
+    async fn reset_password(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>, AppError>;
+
+    /// Sets a new password and revokes every session and personal access token
+    /// of the user, along with any outstanding password reset.
+    async fn change_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        mail: &[Email],
+    ) -> Result<(), AppError>;
+
+    /// Stores a new TOTP secret for the user, replacing an unconfirmed one.
+    /// Returns `false` if the user already confirmed an authenticator.
+    async fn enroll_totp(&self, user_id: i32, secret: &[u8]) -> Result<bool, AppError>;
+
+    async fn get_totp_authenticator(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<TotpAuthenticator>, AppError>;
+
+    /// Confirms the user's authenticator with the time step of its first code,
+    /// and replaces their recovery codes. Returns `false` if there was no
+    /// unconfirmed authenticator.
+    async fn confirm_totp(
+        &self,
+        user_id: i32,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, AppError>;
+
+    /// Records the time step of an accepted code. Returns `false` if a code of
+    /// that step or a later one was already accepted.
+    async fn use_totp_step(&self, user_id: i32, step: i64) -> Result<bool, AppError>;
+
+    /// Marks a recovery code as used. Returns `false` if it is unknown or was
+    /// already used.
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool, AppError>;
+
+    /// Removes the user's authenticator and recovery codes.
+    async fn disable_two_factor(&self, user_id: i32) -> Result<(), AppError>;
+
+    async fn create_login_challenge(
+        &self,
+        token_hash: &str,
+        user_id: i32,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), AppError>;
 
-    async fn is_email_taken(&self, email: &str) -> bool;
+    /// Email of the user of a live challenge, so that attempts at it can be
+    /// limited per account.
+    async fn get_login_challenge_email(&self, token_hash: &str)
+        -> Result<Option<String>, AppError>;
+
+    /// Counts an attempt at a second factor for a live challenge. Returns the
+    /// user of the challenge, or `None` if it is unknown, expired or out of
+    /// attempts.
+    async fn record_login_challenge_attempt(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError>;
+
+    /// Deletes a live challenge once passed. Returns its user, or `None` if it
+    /// was consumed concurrently.
+    async fn consume_login_challenge(&self, token_hash: &str) -> Result<Option<i32>, AppError>;
+
+    /// Stores a passwordless login for the user, replacing the previous one
+    /// unless it was created less than `cooldown_seconds` ago. Returns `false`
+    /// during the cooldown.
+    async fn create_email_login(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+        token_hash: &str,
+        cooldown_seconds: f64,
+        mail: &[Email],
+    ) -> Result<bool, AppError>;
+
+    /// The pending passwordless login of a verified user.
+    async fn get_email_login(&self, email: &str) -> Result<Option<EmailLogin>, AppError>;
+
+    /// Counts an attempt at the user's login code. Returns the number of
+    /// attempts so far, or `None` if the limit has already been reached.
+    async fn record_email_login_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError>;
 
-    async fn get_pending_verification(&self, email: &str) -> Option<PendingEmailVerification>;
+    /// Deletes the user's login if the code matches and it was created less
+    /// than `ttl_seconds` ago. Returns the user, or `None` otherwise.
+    async fn consume_email_login_code(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+        ttl_seconds: f64,
+    ) -> Result<Option<i32>, AppError>;
 
-    async fn verify_email(&self, email: &str) -> Option<AuthUser>;
+    /// Deletes the login of a link token created less than `ttl_seconds` ago.
+    /// Returns its user, or `None` otherwise.
+    async fn consume_email_login_token(
+        &self,
+        token_hash: &str,
+        ttl_seconds: f64,
+    ) -> Result<Option<i32>, AppError>;
 }
 
 #[async_trait]
 impl AuthRepoImpl for AuthRepo {
-    async fn find_user_id_password_by_email(&self, email: &str) -> Option<AuthUser> {
-        sqlx::query_file_as!(AuthUser, "queries/auth/get_user_by_email.sql", email)
+    async fn find_user_id_password_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<AuthUser>, AppError> {
+        let user = sqlx::query_file_as!(AuthUser, "queries/auth/get_user_by_email.sql", email)
             .fetch_optional(&self.db)
-            .await
-            .unwrap()
+            .await?;
+
+        Ok(user)
     }
 
     async fn create_user(
@@ -46,43 +252,491 @@ impl AuthRepoImpl for AuthRepo {
         password_hash: &str,
         name: &str,
         verification_code: &str,
-    ) -> Option<i32> {
-        sqlx::query_file_scalar!(
+        locale: Option<&str>,
+        mail: &[Email],
+    ) -> Result<Option<i32>, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let user_id = sqlx::query_file_scalar!(
             "queries/auth/create_user.sql",
             email,
             password_hash,
             name,
             verification_code,
+            locale,
         )
-        .fetch_optional(&self.db)
-        .await
-        .unwrap()
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(Some(user_id))
     }
 
-    async fn is_email_taken(&self, email: &str) -> bool {
-        sqlx::query_file_scalar!("queries/auth/is_email_taken.sql", email)
+    async fn is_email_taken(&self, email: &str) -> Result<bool, AppError> {
+        let taken = sqlx::query_file_scalar!("queries/auth/is_email_taken.sql", email)
             .fetch_one(&self.db)
-            .await
-            .unwrap()
-            .expect("Query should return a boolean")
+            .await?;
+
+        Ok(taken.expect("Query should return a boolean"))
     }
 
-    async fn get_pending_verification(&self, email: &str) -> Option<PendingEmailVerification> {
-        sqlx::query_file_as!(
+    async fn get_pending_verification(
+        &self,
+        email: &str,
+    ) -> Result<Option<PendingEmailVerification>, AppError> {
+        let pending_verification = sqlx::query_file_as!(
             PendingEmailVerification,
             "queries/auth/get_pending_verification.sql",
             email
         )
         .fetch_optional(&self.db)
-        .await
-        .unwrap()
+        .await?;
+
+        Ok(pending_verification)
+    }
+
+    async fn record_verification_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError> {
+        let attempts = sqlx::query_file_scalar!(
+            "queries/auth/record_verification_attempt.sql",
+            user_id,
+            max_attempts
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    async fn regenerate_verification_code(
+        &self,
+        user_id: i32,
+        code: &str,
+        cooldown_seconds: f64,
+        mail: &[Email],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let regenerated = sqlx::query_file_scalar!(
+            "queries/auth/regenerate_verification_code.sql",
+            user_id,
+            code,
+            cooldown_seconds
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if regenerated.is_none() {
+            return Ok(false);
+        }
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn verify_email(
+        &self,
+        email: &str,
+        mail: &[Email],
+    ) -> Result<Option<AuthUser>, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let user = sqlx::query_file_as!(AuthUser, "queries/auth/verify_email.sql", email)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let Some(user) = user else {
+            return Ok(None);
+        };
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(Some(user))
+    }
+
+    async fn create_session(
+        &self,
+        user_id: i32,
+        client: &ClientInfo,
+        refresh_token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<Uuid, AppError> {
+        let session_id = sqlx::query_file_scalar!(
+            "queries/auth/create_session.sql",
+            user_id,
+            client.device_name,
+            client.user_agent,
+            client.ip_address,
+            refresh_token_hash,
+            expires_at,
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(session_id)
+    }
+
+    async fn list_sessions(
+        &self,
+        user_id: i32,
+        current_session_id: Uuid,
+    ) -> Result<Vec<Session>, AppError> {
+        let sessions = sqlx::query_file_as!(
+            Session,
+            "queries/auth/list_sessions.sql",
+            user_id,
+            current_session_id
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<Option<RefreshedSession>, AppError> {
+        let session = sqlx::query_file_as!(
+            RefreshedSession,
+            "queries/auth/rotate_refresh_token.sql",
+            refresh_token_hash,
+            new_refresh_token_hash,
+            expires_at,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn revoke_reused_refresh_token(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<bool, AppError> {
+        let revoked = sqlx::query_file_scalar!(
+            "queries/auth/revoke_reused_refresh_token.sql",
+            refresh_token_hash
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(revoked.is_some())
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> Result<(), AppError> {
+        sqlx::query_file!("queries/auth/revoke_session.sql", session_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_all_sessions(&self, user_id: i32) -> Result<(), AppError> {
+        sqlx::query_file!("queries/auth/revoke_all_sessions.sql", user_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_session(&self, user_id: i32, session_id: Uuid) -> Result<bool, AppError> {
+        let revoked =
+            sqlx::query_file_scalar!("queries/auth/revoke_user_session.sql", session_id, user_id)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(revoked.is_some())
+    }
+
+    async fn create_password_reset(
+        &self,
+        user_id: i32,
+        token_hash: &str,
+        expires_at: OffsetDateTime,
+        mail: &[Email],
+    ) -> Result<(), AppError> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query_file!(
+            "queries/auth/create_password_reset.sql",
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn reset_password(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<Option<i32>, AppError> {
+        let user_id =
+            sqlx::query_file_scalar!("queries/auth/reset_password.sql", token_hash, password_hash)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(user_id)
     }
 
-    async fn verify_email(&self, email: &str) -> Option<AuthUser> {
-        sqlx::query_file_as!(AuthUser, "queries/auth/verify_email.sql", email)
+    async fn change_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        mail: &[Email],
+    ) -> Result<(), AppError> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query_file!("queries/auth/change_password.sql", user_id, password_hash)
+            .execute(&mut *tx)
+            .await?;
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn enroll_totp(&self, user_id: i32, secret: &[u8]) -> Result<bool, AppError> {
+        let enrolled = sqlx::query_file_scalar!("queries/auth/enroll_totp.sql", user_id, secret)
             .fetch_optional(&self.db)
-            .await
-            .unwrap()
+            .await?;
+
+        Ok(enrolled.is_some())
+    }
+
+    async fn get_totp_authenticator(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<TotpAuthenticator>, AppError> {
+        let authenticator = sqlx::query_file_as!(
+            TotpAuthenticator,
+            "queries/auth/get_totp_authenticator.sql",
+            user_id
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(authenticator)
+    }
+
+    async fn confirm_totp(
+        &self,
+        user_id: i32,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let confirmed = sqlx::query_file_scalar!("queries/auth/confirm_totp.sql", user_id, step)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        if confirmed.is_none() {
+            return Ok(false);
+        }
+
+        sqlx::query_file!(
+            "queries/auth/replace_recovery_codes.sql",
+            user_id,
+            recovery_code_hashes
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: i32, step: i64) -> Result<bool, AppError> {
+        let used = sqlx::query_file_scalar!("queries/auth/use_totp_step.sql", user_id, step)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(used.is_some())
+    }
+
+    async fn use_recovery_code(&self, user_id: i32, code_hash: &str) -> Result<bool, AppError> {
+        let used =
+            sqlx::query_file_scalar!("queries/auth/use_recovery_code.sql", user_id, code_hash)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(used.is_some())
+    }
+
+    async fn disable_two_factor(&self, user_id: i32) -> Result<(), AppError> {
+        sqlx::query_file!("queries/auth/disable_two_factor.sql", user_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_login_challenge(
+        &self,
+        token_hash: &str,
+        user_id: i32,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), AppError> {
+        sqlx::query_file!(
+            "queries/auth/create_login_challenge.sql",
+            token_hash,
+            user_id,
+            expires_at
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_login_challenge_email(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<String>, AppError> {
+        let email =
+            sqlx::query_file_scalar!("queries/auth/get_login_challenge_email.sql", token_hash)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(email)
+    }
+
+    async fn record_login_challenge_attempt(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError> {
+        let user_id = sqlx::query_file_scalar!(
+            "queries/auth/record_login_challenge_attempt.sql",
+            token_hash,
+            max_attempts
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    async fn consume_login_challenge(&self, token_hash: &str) -> Result<Option<i32>, AppError> {
+        let user_id =
+            sqlx::query_file_scalar!("queries/auth/consume_login_challenge.sql", token_hash)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(user_id)
+    }
+
+    async fn create_email_login(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+        token_hash: &str,
+        cooldown_seconds: f64,
+        mail: &[Email],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let created = sqlx::query_file_scalar!(
+            "queries/auth/create_email_login.sql",
+            user_id,
+            code_hash,
+            token_hash,
+            cooldown_seconds
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if created.is_none() {
+            return Ok(false);
+        }
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_email_login(&self, email: &str) -> Result<Option<EmailLogin>, AppError> {
+        let email_login =
+            sqlx::query_file_as!(EmailLogin, "queries/auth/get_email_login.sql", email)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(email_login)
+    }
+
+    async fn record_email_login_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError> {
+        let attempts = sqlx::query_file_scalar!(
+            "queries/auth/record_email_login_attempt.sql",
+            user_id,
+            max_attempts
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    async fn consume_email_login_code(
+        &self,
+        user_id: i32,
+        code_hash: &str,
+        ttl_seconds: f64,
+    ) -> Result<Option<i32>, AppError> {
+        let user_id = sqlx::query_file_scalar!(
+            "queries/auth/consume_email_login_code.sql",
+            user_id,
+            code_hash,
+            ttl_seconds
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    async fn consume_email_login_token(
+        &self,
+        token_hash: &str,
+        ttl_seconds: f64,
+    ) -> Result<Option<i32>, AppError> {
+        let user_id = sqlx::query_file_scalar!(
+            "queries/auth/consume_email_login_token.sql",
+            token_hash,
+            ttl_seconds
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user_id)
     }
 }
 
@@ -90,8 +744,16 @@ impl AuthRepoImpl for AuthRepo {
 mod tests {
     use sqlx::PgPool;
 
+    use std::path::Path;
+
     use super::*;
-    use crate::features::users::repositories::{UserRepo, UserRepoImpl};
+    use crate::{
+        features::{
+            outbox::{repositories::OutboxRepo, worker::deliver_due},
+            users::repositories::{UserRepo, UserRepoImpl},
+        },
+        mail::{MemoryMailer, Message, Templates},
+    };
 
     #[sqlx::test]
     async fn test_create_user(pool: PgPool) {
@@ -99,27 +761,32 @@ mod tests {
         let user_repo = UserRepo { db: pool.clone() };
 
         let id = repo
-            .create_user("a.b@c.com", "abc", "abc", "123")
+            .create_user("a.b@c.com", "abc", "abc", "123", None, &[])
             .await
+            .unwrap()
             .expect("should return user ID");
 
-        repo.create_user("user1@c.com", "abc", "user1", "123")
+        repo.create_user("user1@c.com", "abc", "user1", "123", None, &[])
             .await
+            .unwrap()
             .unwrap();
-        repo.create_user("user2@c.com", "abc", "user2", "123")
+        repo.create_user("user2@c.com", "abc", "user2", "123", None, &[])
             .await
+            .unwrap()
             .unwrap();
-        repo.create_user("user3@c.com", "abc", "user3", "123")
+        repo.create_user("user3@c.com", "abc", "user3", "123", None, &[])
             .await
+            .unwrap()
             .unwrap();
-        repo.create_user("user4@c.com", "abc", "user4", "123")
+        repo.create_user("user4@c.com", "abc", "user4", "123", None, &[])
             .await
+            .unwrap()
             .unwrap();
 
         let user = sqlx::query_as!(
             AuthUser,
             r#"
-            SELECT id, username, email, password_hash, is_verified
+            SELECT id, username, email, password_hash, is_verified, locale
             FROM gossip_user
             WHERE id = $1
             "#r,
@@ -133,14 +800,15 @@ mod tests {
         assert_eq!(user.password_hash, "abc");
 
         let id = repo
-            .create_user("a.b@c.com", "def", "abc", "123")
+            .create_user("a.b@c.com", "def", "abc", "123", None, &[])
             .await
+            .unwrap()
             .expect("should return user ID");
 
         let user = sqlx::query_as!(
             AuthUser,
             r#"
-            SELECT id, username, email, password_hash, is_verified
+            SELECT id, username, email, password_hash, is_verified, locale
             FROM gossip_user
             WHERE id = $1
             "#r,
@@ -163,18 +831,22 @@ mod tests {
         .unwrap();
 
         let user = user_repo
-            .find_by_email("a.b@c.com")
+            .find_by_email("a.b@c.com", Some(id))
             .await
+            .unwrap()
             .expect("should return user");
 
-        let id = repo.create_user("a.b@c.com", "ghi", "abc", "123").await;
+        let id = repo
+            .create_user("a.b@c.com", "ghi", "abc", "123", None, &[])
+            .await
+            .unwrap();
 
         assert_eq!(id, None);
 
         let user = sqlx::query_as!(
             AuthUser,
             r#"
-            SELECT id, username, email, password_hash, is_verified
+            SELECT id, username, email, password_hash, is_verified, locale
             FROM gossip_user
             WHERE id = $1
             "#r,
@@ -193,19 +865,21 @@ mod tests {
         let repo = AuthRepo { db: pool.clone() };
 
         let id = repo
-            .create_user("a.b@c.com", "abc", "me", "123456")
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
             .await
+            .unwrap()
             .expect("should return user ID");
 
-        let verification = repo.get_pending_verification("a.b@c.com").await;
+        let verification = repo.get_pending_verification("a.b@c.com").await.unwrap();
         assert!(verification.is_some());
 
         let user = repo
-            .verify_email("a.b@c.com")
+            .verify_email("a.b@c.com", &[])
             .await
+            .unwrap()
             .expect("should return user");
 
-        let verification = repo.get_pending_verification("a.b@c.com").await;
+        let verification = repo.get_pending_verification("a.b@c.com").await.unwrap();
         assert!(verification.is_none());
 
         assert_eq!(user.id, id);
@@ -216,7 +890,7 @@ mod tests {
         let user = sqlx::query_as!(
             AuthUser,
             r#"
-            SELECT id, email, username, password_hash, is_verified
+            SELECT id, email, username, password_hash, is_verified, locale
             FROM gossip_user WHERE id = $1
             "#r,
             id
@@ -227,4 +901,791 @@ mod tests {
 
         assert!(user.is_verified);
     }
+
+    #[sqlx::test]
+    async fn test_rotate_refresh_token(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        let session_id = repo
+            .create_session(user_id, &ClientInfo::default(), "first", expires_at)
+            .await
+            .unwrap();
+
+        let refreshed = repo
+            .rotate_refresh_token("first", "second", expires_at)
+            .await
+            .unwrap()
+            .expect("should rotate a fresh token");
+
+        assert_eq!(refreshed.session_id, session_id);
+        assert_eq!(refreshed.user_id, user_id);
+
+        let refreshed = repo
+            .rotate_refresh_token("second", "third", expires_at)
+            .await
+            .unwrap()
+            .expect("should rotate the replacement token");
+
+        assert_eq!(refreshed.session_id, session_id);
+
+        assert!(repo
+            .rotate_refresh_token("unknown", "fourth", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!repo.revoke_reused_refresh_token("unknown").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_reused_refresh_token_revokes_session(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        repo.create_session(user_id, &ClientInfo::default(), "first", expires_at)
+            .await
+            .unwrap();
+        repo.rotate_refresh_token("first", "second", expires_at)
+            .await
+            .unwrap()
+            .expect("should rotate a fresh token");
+
+        assert!(repo
+            .rotate_refresh_token("first", "third", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo.revoke_reused_refresh_token("first").await.unwrap());
+
+        // The legitimate successor is now unusable as well.
+        assert!(repo
+            .rotate_refresh_token("second", "third", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!repo.revoke_reused_refresh_token("second").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_expired_refresh_token_is_rejected(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let now = OffsetDateTime::now_utc();
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        repo.create_session(
+            user_id,
+            &ClientInfo::default(),
+            "first",
+            now - time::Duration::seconds(1),
+        )
+        .await
+        .unwrap();
+
+        assert!(repo
+            .rotate_refresh_token("first", "second", now)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!repo.revoke_reused_refresh_token("first").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_revoke_session(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        let phone = repo
+            .create_session(user_id, &ClientInfo::default(), "phone", expires_at)
+            .await
+            .unwrap();
+        let laptop = repo
+            .create_session(user_id, &ClientInfo::default(), "laptop", expires_at)
+            .await
+            .unwrap();
+
+        repo.revoke_session(phone).await.unwrap();
+
+        let live_sessions = sqlx::query_scalar!(
+            "SELECT id FROM auth_session WHERE user_id = $1 AND revoked_at IS NULL",
+            user_id
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(live_sessions, vec![laptop]);
+        assert!(repo
+            .rotate_refresh_token("phone", "phone2", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+
+        repo.revoke_all_sessions(user_id).await.unwrap();
+
+        assert!(repo
+            .rotate_refresh_token("laptop", "laptop2", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_list_and_revoke_user_sessions(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let now = OffsetDateTime::now_utc();
+        let expires_at = now + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .unwrap();
+        let other_id = repo
+            .create_user("d.e@f.com", "abc", "you", "123456", None, &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        let phone = repo
+            .create_session(
+                user_id,
+                &ClientInfo {
+                    device_name: Some("Ann's phone".to_owned()),
+                    user_agent: Some("gossip-ios/2.1".to_owned()),
+                    ip_address: Some("203.0.113.7".to_owned()),
+                },
+                "phone",
+                expires_at,
+            )
+            .await
+            .unwrap();
+        let laptop = repo
+            .create_session(user_id, &ClientInfo::default(), "laptop", expires_at)
+            .await
+            .unwrap();
+        // Expired sessions are left out.
+        repo.create_session(user_id, &ClientInfo::default(), "old", now)
+            .await
+            .unwrap();
+        let other = repo
+            .create_session(other_id, &ClientInfo::default(), "other", expires_at)
+            .await
+            .unwrap();
+
+        // Refreshing counts as using the session.
+        repo.rotate_refresh_token("laptop", "laptop2", expires_at)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let sessions = repo.list_sessions(user_id, phone).await.unwrap();
+        let ids = sessions
+            .iter()
+            .map(|session| session.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![laptop, phone]);
+        assert!(!sessions[0].current);
+        assert!(sessions[1].current);
+        assert_eq!(sessions[1].device_name.as_deref(), Some("Ann's phone"));
+        assert_eq!(sessions[1].user_agent.as_deref(), Some("gossip-ios/2.1"));
+        assert_eq!(sessions[1].ip_address.as_deref(), Some("203.0.113.7"));
+
+        // Sessions of other users can't be revoked.
+        assert!(!repo.revoke_user_session(user_id, other).await.unwrap());
+        assert_eq!(repo.list_sessions(other_id, other).await.unwrap().len(), 1);
+
+        assert!(repo.revoke_user_session(user_id, laptop).await.unwrap());
+        assert!(!repo.revoke_user_session(user_id, laptop).await.unwrap());
+
+        let ids = repo
+            .list_sessions(user_id, phone)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|session| session.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![phone]);
+    }
+
+    #[sqlx::test]
+    async fn test_reset_password(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.create_session(user_id, &ClientInfo::default(), "refresh", expires_at)
+            .await
+            .unwrap();
+
+        repo.create_password_reset(user_id, "first", expires_at, &[])
+            .await
+            .unwrap();
+        repo.create_password_reset(user_id, "second", expires_at, &[])
+            .await
+            .unwrap();
+
+        // Only the latest reset token is valid.
+        assert_eq!(repo.reset_password("first", "def").await.unwrap(), None);
+        assert_eq!(
+            repo.reset_password("second", "def").await.unwrap(),
+            Some(user_id)
+        );
+
+        // Reset tokens are single-use.
+        assert_eq!(repo.reset_password("second", "ghi").await.unwrap(), None);
+
+        let user = repo
+            .find_user_id_password_by_email("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.password_hash, "def");
+
+        // Sessions started before the reset are no longer usable.
+        assert!(repo
+            .rotate_refresh_token("refresh", "refresh2", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_expired_password_reset_is_rejected(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        repo.create_password_reset(
+            user_id,
+            "token",
+            OffsetDateTime::now_utc() - time::Duration::seconds(1),
+            &[],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(repo.reset_password("token", "def").await.unwrap(), None);
+    }
+
+    #[sqlx::test]
+    async fn test_change_password(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.create_session(user_id, &ClientInfo::default(), "refresh", expires_at)
+            .await
+            .unwrap();
+        repo.create_password_reset(user_id, "reset", expires_at, &[])
+            .await
+            .unwrap();
+
+        repo.change_password(user_id, "def", &[]).await.unwrap();
+
+        let user = repo
+            .find_user_id_password_by_email("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.password_hash, "def");
+
+        assert!(repo
+            .rotate_refresh_token("refresh", "refresh2", expires_at)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(repo.reset_password("reset", "ghi").await.unwrap(), None);
+    }
+
+    #[sqlx::test]
+    async fn test_registration_emails_the_verification_code(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let outbox_repo = Arc::new(OutboxRepo { db: pool.clone() });
+        let mailer = Arc::new(MemoryMailer::default());
+        let templates = Templates::load(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"),
+            "en",
+        )
+        .unwrap();
+
+        let verification_email = templates.render(
+            &Message::Verification { code: "123456" },
+            Some("es"),
+            "me",
+            "a.b@c.com",
+        );
+
+        repo.create_user(
+            "a.b@c.com",
+            "abc",
+            "me",
+            "123456",
+            Some("es"),
+            &[verification_email],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // Nothing is sent until the outbox is drained.
+        assert!(mailer.sent().is_empty());
+        assert_eq!(
+            deliver_due(outbox_repo, mailer.clone(), 3, 30)
+                .await
+                .unwrap(),
+            1
+        );
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to_address, "a.b@c.com");
+        assert_eq!(sent[0].to_name, "me");
+        assert!(sent[0].text_body.contains("123456"));
+        assert!(sent[0].html_body.contains("123456"));
+    }
+
+    #[sqlx::test]
+    async fn test_verification_attempts_are_limited(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        assert_eq!(
+            repo.record_verification_attempt(user_id, 2).await.unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            repo.record_verification_attempt(user_id, 2).await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            repo.record_verification_attempt(user_id, 2).await.unwrap(),
+            None
+        );
+
+        let verification = repo
+            .get_pending_verification("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(verification.attempts, 2);
+
+        // Registering again issues a fresh code with a clean slate.
+        repo.create_user("a.b@c.com", "abc", "me", "654321", None, &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        let verification = repo
+            .get_pending_verification("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(verification.code, "654321");
+        assert_eq!(verification.attempts, 0);
+        assert_eq!(
+            repo.record_verification_attempt(user_id, 2).await.unwrap(),
+            Some(1)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_regenerate_verification_code(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.record_verification_attempt(user_id, 5).await.unwrap();
+
+        assert!(!repo
+            .regenerate_verification_code(user_id, "654321", 60.0, &[])
+            .await
+            .unwrap());
+
+        sqlx::query!(
+            "UPDATE pending_email_verification SET created_at = now() - interval '2 minutes'"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(repo
+            .regenerate_verification_code(user_id, "654321", 60.0, &[])
+            .await
+            .unwrap());
+
+        let verification = repo
+            .get_pending_verification("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(verification.code, "654321");
+        assert_eq!(verification.attempts, 0);
+
+        let user = repo
+            .find_user_id_password_by_email("a.b@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.password_hash, "abc");
+        assert_eq!(user.username, "me");
+    }
+
+    #[sqlx::test]
+    async fn test_create_user_queues_email_only_if_created(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let mail = [Email::new(
+            "me",
+            "a.b@c.com",
+            "Your code",
+            "123456",
+            "123456",
+        )];
+
+        repo.create_user("a.b@c.com", "abc", "me", "123456", None, &mail)
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.verify_email("a.b@c.com", &[]).await.unwrap().unwrap();
+
+        assert!(repo
+            .create_user("a.b@c.com", "abc", "me", "654321", None, &mail)
+            .await
+            .unwrap()
+            .is_none());
+
+        let queued = sqlx::query_scalar!("SELECT html_body FROM email_outbox")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(queued, vec!["123456"]);
+    }
+
+    #[sqlx::test]
+    async fn test_totp_enrollment(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        assert!(repo
+            .get_totp_authenticator(user_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Enrolling again before confirming replaces the secret.
+        assert!(repo.enroll_totp(user_id, b"first").await.unwrap());
+        assert!(repo.enroll_totp(user_id, b"second").await.unwrap());
+
+        let authenticator = repo.get_totp_authenticator(user_id).await.unwrap().unwrap();
+        assert_eq!(authenticator.secret, b"second");
+        assert!(!authenticator.is_confirmed);
+
+        // Codes aren't accepted before confirmation.
+        assert!(!repo.use_totp_step(user_id, 10).await.unwrap());
+
+        let hashes = vec!["one".to_owned(), "two".to_owned()];
+        assert!(repo.confirm_totp(user_id, 10, &hashes).await.unwrap());
+        assert!(!repo.confirm_totp(user_id, 11, &hashes).await.unwrap());
+        assert!(!repo.enroll_totp(user_id, b"third").await.unwrap());
+
+        let authenticator = repo.get_totp_authenticator(user_id).await.unwrap().unwrap();
+        assert_eq!(authenticator.secret, b"second");
+        assert!(authenticator.is_confirmed);
+
+        // The confirmation code, or any earlier one, can't be replayed.
+        assert!(!repo.use_totp_step(user_id, 10).await.unwrap());
+        assert!(!repo.use_totp_step(user_id, 9).await.unwrap());
+        assert!(repo.use_totp_step(user_id, 11).await.unwrap());
+        assert!(!repo.use_totp_step(user_id, 11).await.unwrap());
+
+        repo.disable_two_factor(user_id).await.unwrap();
+
+        assert!(repo
+            .get_totp_authenticator(user_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!repo.use_recovery_code(user_id, "one").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_recovery_codes_are_single_use(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        let other_user_id = repo
+            .create_user("d.e@f.com", "abc", "other", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        repo.enroll_totp(user_id, b"secret").await.unwrap();
+        repo.confirm_totp(user_id, 10, &["one".to_owned(), "two".to_owned()])
+            .await
+            .unwrap();
+
+        assert!(!repo.use_recovery_code(other_user_id, "one").await.unwrap());
+        assert!(repo.use_recovery_code(user_id, "one").await.unwrap());
+        assert!(!repo.use_recovery_code(user_id, "one").await.unwrap());
+        assert!(!repo.use_recovery_code(user_id, "three").await.unwrap());
+        assert!(repo.use_recovery_code(user_id, "two").await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_login_challenge(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let expires_at = OffsetDateTime::now_utc() + time::Duration::minutes(5);
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        repo.create_login_challenge("challenge", user_id, expires_at)
+            .await
+            .unwrap();
+        repo.create_login_challenge(
+            "expired",
+            user_id,
+            OffsetDateTime::now_utc() - time::Duration::seconds(1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            repo.get_login_challenge_email("challenge")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("a.b@c.com")
+        );
+        assert_eq!(
+            repo.get_login_challenge_email("expired").await.unwrap(),
+            None
+        );
+
+        for _ in 0..2 {
+            assert_eq!(
+                repo.record_login_challenge_attempt("challenge", 2)
+                    .await
+                    .unwrap(),
+                Some(user_id)
+            );
+        }
+
+        assert_eq!(
+            repo.record_login_challenge_attempt("challenge", 2)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            repo.record_login_challenge_attempt("expired", 2)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(repo.consume_login_challenge("expired").await.unwrap(), None);
+
+        assert_eq!(
+            repo.consume_login_challenge("challenge").await.unwrap(),
+            Some(user_id)
+        );
+        assert_eq!(
+            repo.consume_login_challenge("challenge").await.unwrap(),
+            None
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_email_login_is_single_use(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+
+        // Unverified users can't log in by email.
+        assert!(repo
+            .create_email_login(user_id, "code", "token", 0.0, &[])
+            .await
+            .unwrap());
+        assert!(repo.get_email_login("a.b@c.com").await.unwrap().is_none());
+
+        repo.verify_email("a.b@c.com", &[]).await.unwrap().unwrap();
+
+        let email_login = repo.get_email_login("a.b@c.com").await.unwrap().unwrap();
+        assert_eq!(email_login.user_id, user_id);
+        assert_eq!(email_login.attempts, 0);
+
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "wrong", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "code", 600.0)
+                .await
+                .unwrap(),
+            Some(user_id)
+        );
+
+        // The link of a used code is gone too.
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "code", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            repo.consume_email_login_token("token", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+
+        repo.create_email_login(user_id, "code", "token", 0.0, &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.consume_email_login_token("token", 600.0)
+                .await
+                .unwrap(),
+            Some(user_id)
+        );
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "code", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_email_login_expires(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.verify_email("a.b@c.com", &[]).await.unwrap().unwrap();
+
+        repo.create_email_login(user_id, "code", "token", 0.0, &[])
+            .await
+            .unwrap();
+        sqlx::query!(
+            "UPDATE email_login SET created_at = now() - interval '11 minutes' WHERE user_id = $1",
+            user_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "code", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            repo.consume_email_login_token("token", 600.0)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_email_login_cooldown(pool: PgPool) {
+        let repo = AuthRepo { db: pool.clone() };
+        let mail = [Email::new("me", "a.b@c.com", "Log in", "first", "first")];
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
+            .await
+            .unwrap()
+            .expect("should return user ID");
+        repo.verify_email("a.b@c.com", &[]).await.unwrap().unwrap();
+
+        assert!(repo
+            .create_email_login(user_id, "first", "first", 60.0, &mail)
+            .await
+            .unwrap());
+        assert!(!repo
+            .create_email_login(user_id, "second", "second", 60.0, &mail)
+            .await
+            .unwrap());
+
+        // The code sent first still works, and was the only one sent.
+        assert_eq!(
+            repo.consume_email_login_code(user_id, "first", 600.0)
+                .await
+                .unwrap(),
+            Some(user_id)
+        );
+
+        let queued = sqlx::query_scalar!("SELECT COUNT(*) FROM email_outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(queued, Some(1));
+    }
 }
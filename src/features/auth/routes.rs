@@ -1,37 +1,104 @@
 use std::sync::Arc;
 
+use anyhow::{anyhow, Context};
+
 use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
-use axum::{extract::State, http::StatusCode, routing::post, Extension, Json, Router};
-use mail_send::{mail_builder::MessageBuilder, SmtpClientBuilder};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
 use rand::Rng;
+use reqwest::Url;
+use subtle::ConstantTimeEq;
+use time::{Duration, OffsetDateTime};
 use tokio::spawn;
+use uuid::Uuid;
 
-use crate::{features::auth::repositories::AuthRepoImpl, jwt, state::AppState};
+use crate::{
+    config::Config,
+    error::AppError,
+    extract::{Json, Path},
+    features::auth::repositories::AuthRepoImpl,
+    jwt,
+    mail::{self, Message},
+    rate_limit::{RateLimiter, Route},
+    state::AppState,
+    token, totp,
+};
 
 use super::{
-    models::{LoginRequest, LoginResponse, RegisterRequest, VerifyEmailRequest},
+    models::{
+        AuthUser, ChangePasswordRequest, ClientInfo, ConfirmTotpRequest, DisableTwoFactorRequest,
+        EmailLoginConfirmRequest, EmailLoginRequest, LoginRequest, LoginResponse, OptionalAuth,
+        PasswordResetConfirmRequest, PasswordResetRequest, Principal, RecoveryCodes,
+        RefreshRequest, RegisterRequest, ResendVerificationRequest, Session, TokenClaims,
+        TotpEnrollment, TwoFactorChallenge, TwoFactorLoginRequest, VerifyEmailRequest,
+    },
     repositories::{self, AuthRepoExt},
 };
 
 pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
         .route("/login", post(login))
+        .route("/login/2fa", post(login_two_factor))
+        .route("/login/email", post(request_email_login))
+        .route("/login/email/confirm", post(confirm_email_login))
         .route("/register", post(register))
         .route("/verify", post(verify_email))
+        .route("/verify/resend", post(resend_verification_code))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        .route("/password-reset/request", post(request_password_reset))
+        .route("/password-reset/confirm", post(confirm_password_reset))
+        .route("/password", put(change_password))
+        .route("/2fa/totp", post(enroll_totp))
+        .route("/2fa/totp/confirm", post(confirm_totp))
+        .route("/2fa/disable", post(disable_two_factor))
         .layer(Extension(Arc::new(repositories::AuthRepo {
             db: state.db.clone(),
         })))
 }
 
+/// Routes served under `/.well-known`.
+pub fn well_known_router() -> Router<Arc<AppState>> {
+    Router::new().route("/jwks.json", get(jwks))
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "JSON Web Key Set of the public keys access tokens are verified with, looked up by the `kid` header of a token."),
+    ),
+    tag = "auth",
+)]
+async fn jwks(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    // Long enough to spare the verifiers, short enough for a new key to be
+    // picked up before it starts signing.
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.jwt_keys.jwks().clone()),
+    )
+}
+
 #[utoipa::path(
     post,
     path = "/auth/login",
     responses(
         (status = 200, body = LoginResponse),
-        (status = 401, description = "Invalid email or password."),
+        (status = 202, body = TwoFactorChallenge, description = "Password accepted, a second factor must be sent to `/auth/login/2fa`."),
+        (status = 401, body = ProblemDetails, description = "Invalid email or password."),
+        (status = 403, body = ProblemDetails, description = "Email not verified."),
+        (status = 429, body = ProblemDetails, description = "Too many requests or failed logins, retry after the number of seconds in `Retry-After`."),
     ),
     request_body = LoginRequest,
     tag = "auth",
@@ -39,27 +106,182 @@ pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
 async fn login(
     State(state): State<Arc<AppState>>,
     Extension(repo): Extension<AuthRepoExt>,
+    client: ClientInfo,
     Json(LoginRequest { email, password }): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<Response, AppError> {
     let argon2 = Argon2::default();
+    let rate_limiter = &state.rate_limiter;
 
-    let user = repo
-        .find_user_id_password_by_email(&email)
-        .await
-        .ok_or_else(|| StatusCode::UNAUTHORIZED)?;
+    rate_limiter
+        .check(Route::Login, client.ip_address.as_deref(), Some(&email))
+        .await?;
+    rate_limiter.check_lockout(&email).await?;
 
-    let hash =
-        PasswordHash::new(&user.password_hash).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Failures count the same whether the account exists or not.
+    let Some(user) = repo.find_user_id_password_by_email(&email).await? else {
+        rate_limiter.record_login_failure(&email).await?;
+        return Err(AppError::InvalidCredentials);
+    };
 
-    match argon2.verify_password(password.as_ref(), &hash) {
-        Ok(()) => {
-            let token = jwt::encode(user.id, state.config.jwt_secret.as_ref())
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let hash = parse_password_hash(&user.password_hash)?;
 
-            Ok(Json(LoginResponse { token }))
-        }
-        _ => Err(StatusCode::UNAUTHORIZED),
+    if argon2.verify_password(password.as_ref(), &hash).is_err() {
+        rate_limiter.record_login_failure(&email).await?;
+        return Err(AppError::InvalidCredentials);
+    }
+
+    rate_limiter.clear_login_failures(&email).await?;
+
+    if !user.is_verified {
+        return Err(AppError::EmailNotVerified);
     }
+
+    finish_login(&repo, &state, user.id, &client).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login/email",
+    responses(
+        (status = 202, description = "A login code, and a link if the app has a page for it, is emailed if a verified account exists for the address."),
+        (status = 429, body = ProblemDetails, description = "Too many requests, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = EmailLoginRequest,
+    tag = "auth",
+)]
+async fn request_email_login(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<EmailLoginRequest>,
+) -> Result<StatusCode, AppError> {
+    state
+        .rate_limiter
+        .check(
+            Route::EmailLogin,
+            client.ip_address.as_deref(),
+            Some(&body.email),
+        )
+        .await?;
+
+    let config = state.config.clone();
+    let templates = state.templates.clone();
+
+    // Everything happens in the background so that neither the response nor
+    // its timing reveals whether the address belongs to an account.
+    spawn(async move {
+        let user = match repo.find_user_id_password_by_email(&body.email).await {
+            Ok(Some(user)) if user.is_verified => user,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::error!("Failed to look up user for email login: {:?}", e);
+                return;
+            }
+        };
+
+        let mut rng = rand::rngs::OsRng;
+        let code = rng.gen_range(100000..999999).to_string();
+        let login_token = token::generate();
+
+        let email = templates.render(
+            &Message::EmailLogin {
+                code: &code,
+                link: config
+                    .email_login_url
+                    .as_ref()
+                    .map(|url| link_with_token(url, &login_token)),
+                expires_in_minutes: config.email_login_ttl / 60,
+            },
+            user.locale.as_deref(),
+            &user.username,
+            &user.email,
+        );
+
+        // Nothing is sent during the cooldown, the previous code still works.
+        let result = repo
+            .create_email_login(
+                user.id,
+                &token::hash(&code),
+                &token::hash(&login_token),
+                config.email_login_resend_cooldown as f64,
+                &[email],
+            )
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to create email login: {:?}", e);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login/email/confirm",
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 202, body = TwoFactorChallenge, description = "Code accepted, a second factor must be sent to `/auth/login/2fa`."),
+        (status = 400, body = ProblemDetails, description = "Invalid code or link."),
+        (status = 410, body = ProblemDetails, description = "Code expired, request a new one."),
+        (status = 429, body = ProblemDetails, description = "Too many attempts, request a new code. Or too many requests, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = EmailLoginConfirmRequest,
+    tag = "auth",
+)]
+async fn confirm_email_login(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<EmailLoginConfirmRequest>,
+) -> Result<Response, AppError> {
+    let config = &state.config;
+    let ttl = config.email_login_ttl as f64;
+
+    let email = match &body {
+        EmailLoginConfirmRequest::Code { email, .. } => Some(email.as_str()),
+        EmailLoginConfirmRequest::Link { .. } => None,
+    };
+
+    state
+        .rate_limiter
+        .check(Route::EmailLogin, client.ip_address.as_deref(), email)
+        .await?;
+
+    let user_id = match body {
+        EmailLoginConfirmRequest::Code { email, code } => {
+            let email_login = repo
+                .get_email_login(&email)
+                .await?
+                .ok_or(AppError::InvalidCode)?;
+
+            if email_login.attempts >= config.email_login_max_attempts {
+                return Err(AppError::TooManyAttempts);
+            }
+
+            let expires_at =
+                email_login.created_at + Duration::seconds(config.email_login_ttl as i64);
+
+            if expires_at <= OffsetDateTime::now_utc() {
+                return Err(AppError::CodeExpired);
+            }
+
+            repo.record_email_login_attempt(email_login.user_id, config.email_login_max_attempts)
+                .await?
+                .ok_or(AppError::TooManyAttempts)?;
+
+            repo.consume_email_login_code(email_login.user_id, &token::hash(code.trim()), ttl)
+                .await?
+        }
+        EmailLoginConfirmRequest::Link { token } => {
+            repo.consume_email_login_token(&token::hash(&token), ttl)
+                .await?
+        }
+    };
+
+    let user_id = user_id.ok_or(AppError::InvalidCode)?;
+
+    finish_login(&repo, &state, user_id, &client).await
 }
 
 #[utoipa::path(
@@ -67,7 +289,9 @@ async fn login(
     path = "/auth/register",
     responses(
         (status = 201, description = "Verification email sent."),
-        (status = 409, description = "Email already taken."),
+        (status = 400, body = ProblemDetails, description = "Invalid locale."),
+        (status = 409, body = ProblemDetails, description = "Email already taken."),
+        (status = 429, body = ProblemDetails, description = "Too many requests, retry after the number of seconds in `Retry-After`."),
     ),
     request_body = RegisterRequest,
     tag = "auth",
@@ -75,63 +299,59 @@ async fn login(
 async fn register(
     Extension(repo): Extension<AuthRepoExt>,
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(body): Json<RegisterRequest>,
-) -> StatusCode {
-    if repo.is_email_taken(&body.email).await {
-        return StatusCode::CONFLICT;
-    }
-
-    let config = state.config.clone();
+) -> Result<StatusCode, AppError> {
+    state
+        .rate_limiter
+        .check(
+            Route::Register,
+            client.ip_address.as_deref(),
+            Some(&body.email),
+        )
+        .await?;
 
-    let argon2 = Argon2::default();
-    let salt = SaltString::generate(&mut OsRng);
+    if let Some(locale) = &body.locale {
+        if !mail::is_valid_locale(locale) {
+            return Err(AppError::InvalidRequest(format!(
+                "{locale} is not a valid locale."
+            )));
+        }
+    }
 
-    let password_hash = argon2.hash_password(body.password.as_ref(), &salt);
+    if repo.is_email_taken(&body.email).await? {
+        return Err(AppError::EmailTaken);
+    }
 
-    let password_hash = match password_hash {
-        Ok(hash) => hash.to_string(),
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-    };
+    let password_hash = hash_password(&body.password)?;
 
     let mut rng = rand::rngs::OsRng;
-    let verification_code = rng.gen_range(100000..999999);
+    let verification_code = rng.gen_range(100000..999999).to_string();
+
+    let verification_email = state.templates.render(
+        &Message::Verification {
+            code: &verification_code,
+        },
+        body.locale.as_deref(),
+        &body.name,
+        &body.email,
+    );
 
     let user_id = repo
         .create_user(
             &body.email,
             &password_hash,
             &body.name,
-            verification_code.to_string().as_str(),
+            &verification_code,
+            body.locale.as_deref(),
+            &[verification_email],
         )
-        .await;
-
-    let message = MessageBuilder::new()
-        .from((config.mail_author, config.mail_email))
-        .to(("", body.email.as_str()))
-        .subject("Your Gossip verification code")
-        .html_body(format!(
-            r#"Your verification code is: {}
-
-            <br><br>
-
-            If you didn't request this code, please ignore this email. 
-            "#,
-            verification_code
-        ));
-
-    SmtpClientBuilder::new(config.mail_host.as_str(), config.mail_port)
-        .implicit_tls(config.mail_tls)
-        .credentials((config.mail_username.as_str(), config.mail_password.as_str()))
-        .connect()
-        .await
-        .unwrap()
-        .send(message)
-        .await
-        .unwrap();
+        .await?;
 
     match user_id {
-        Some(_) => StatusCode::CREATED,
-        None => StatusCode::INTERNAL_SERVER_ERROR,
+        Some(_) => Ok(StatusCode::CREATED),
+        // Verified in the meantime by a concurrent registration.
+        None => Err(AppError::EmailTaken),
     }
 }
 
@@ -140,7 +360,9 @@ async fn register(
     path = "/auth/verify",
     responses(
         (status = 200, body = LoginResponse),
-        (status = 401, description = "Invalid verification code."),
+        (status = 400, body = ProblemDetails, description = "Invalid verification code."),
+        (status = 410, body = ProblemDetails, description = "Verification code expired, request a new one."),
+        (status = 429, body = ProblemDetails, description = "Too many attempts, request a new code. Or too many requests, retry after the number of seconds in `Retry-After`."),
     ),
     request_body = VerifyEmailRequest,
     tag = "auth",
@@ -148,44 +370,886 @@ async fn register(
 async fn verify_email(
     Extension(repo): Extension<AuthRepoExt>,
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(body): Json<VerifyEmailRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
+) -> Result<Json<LoginResponse>, AppError> {
     let config = state.config.clone();
 
+    state
+        .rate_limiter
+        .check(
+            Route::Verify,
+            client.ip_address.as_deref(),
+            Some(&body.email),
+        )
+        .await?;
+
     let pending_verification = repo
         .get_pending_verification(&body.email)
-        .await
-        .ok_or_else(|| StatusCode::UNAUTHORIZED)?;
+        .await?
+        .ok_or(AppError::InvalidCode)?;
+
+    if pending_verification.attempts >= config.verification_max_attempts {
+        return Err(AppError::TooManyAttempts);
+    }
+
+    let expires_at =
+        pending_verification.created_at + Duration::seconds(config.verification_code_ttl as i64);
 
-    if pending_verification.code != body.code {
-        return Err(StatusCode::UNAUTHORIZED);
+    if expires_at <= OffsetDateTime::now_utc() {
+        return Err(AppError::CodeExpired);
     }
 
+    repo.record_verification_attempt(
+        pending_verification.user_id,
+        config.verification_max_attempts,
+    )
+    .await?
+    .ok_or(AppError::TooManyAttempts)?;
+
+    let code_matches: bool = pending_verification
+        .code
+        .as_bytes()
+        .ct_eq(body.code.as_bytes())
+        .into();
+
+    if !code_matches {
+        return Err(AppError::InvalidCode);
+    }
+
+    let welcome_email = state.templates.render(
+        &Message::Welcome,
+        pending_verification.locale.as_deref(),
+        &pending_verification.username,
+        &body.email,
+    );
+
     let user = repo
-        .verify_email(&body.email)
-        .await
-        .ok_or_else(|| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .verify_email(&body.email, &[welcome_email])
+        .await?
+        .ok_or(AppError::InvalidCode)?;
+
+    let response = start_session(&repo, &state, user.id, &client).await?;
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/verify/resend",
+    responses(
+        (status = 202, description = "A new verification code is emailed if an unverified account exists for the address, and its last code wasn't sent recently."),
+        (status = 429, body = ProblemDetails, description = "Too many requests, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = ResendVerificationRequest,
+    tag = "auth",
+)]
+async fn resend_verification_code(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<ResendVerificationRequest>,
+) -> Result<StatusCode, AppError> {
+    let config = state.config.clone();
+
+    state
+        .rate_limiter
+        .check(
+            Route::Verify,
+            client.ip_address.as_deref(),
+            Some(&body.email),
+        )
+        .await?;
+
+    let Some(pending_verification) = repo.get_pending_verification(&body.email).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    // Within the cooldown nothing is sent, but the response is the same as for
+    // unknown addresses so that it doesn't reveal pending registrations.
+    let cooldown = Duration::seconds(config.verification_resend_cooldown as i64);
+
+    if pending_verification.created_at + cooldown > OffsetDateTime::now_utc() {
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    let mut rng = rand::rngs::OsRng;
+    let verification_code = rng.gen_range(100000..999999).to_string();
+
+    let verification_email = state.templates.render(
+        &Message::Verification {
+            code: &verification_code,
+        },
+        pending_verification.locale.as_deref(),
+        &pending_verification.username,
+        &body.email,
+    );
+
+    repo.regenerate_verification_code(
+        pending_verification.user_id,
+        &verification_code,
+        cooldown.as_seconds_f64(),
+        &[verification_email],
+    )
+    .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 401, body = ProblemDetails, description = "Invalid, expired or reused refresh token."),
+    ),
+    request_body = RefreshRequest,
+    tag = "auth",
+)]
+async fn refresh(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<RefreshRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let config = &state.config;
+
+    let refresh_token_hash = token::hash(&body.refresh_token);
+    let new_refresh_token = token::generate();
+
+    let session = repo
+        .rotate_refresh_token(
+            &refresh_token_hash,
+            &token::hash(&new_refresh_token),
+            refresh_token_expiry(config),
+        )
+        .await?;
+
+    let Some(session) = session else {
+        if repo
+            .revoke_reused_refresh_token(&refresh_token_hash)
+            .await?
+        {
+            tracing::warn!("Refresh token reuse detected, session revoked");
+        }
+
+        return Err(AppError::InvalidRefreshToken);
+    };
+
+    Ok(Json(LoginResponse {
+        token: access_token(&state, session.user_id, session.session_id)?,
+        refresh_token: new_refresh_token,
+        expires_in: config.access_token_ttl,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    responses(
+        (status = 204, description = "Current session revoked. Nothing happens without a token."),
+        (status = 401, body = ProblemDetails, description = "Invalid token."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+    ),
+    tag = "auth",
+    security(
+        (),
+        ("api_key" = [])
+    )
+)]
+async fn logout(
+    Extension(repo): Extension<AuthRepoExt>,
+    OptionalAuth(principal): OptionalAuth,
+) -> Result<StatusCode, AppError> {
+    if let Some(principal) = principal {
+        repo.revoke_session(principal.session_id()?).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout-all",
+    responses(
+        (status = 204, description = "All sessions of the user revoked."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn logout_all(
+    Extension(repo): Extension<AuthRepoExt>,
+    principal: Principal,
+) -> Result<StatusCode, AppError> {
+    principal.session_id()?;
+
+    repo.revoke_all_sessions(principal.user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    responses(
+        (status = 200, body = [Session], description = "Sessions of the user that can still be used, most recently used first."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn list_sessions(
+    Extension(repo): Extension<AuthRepoExt>,
+    principal: Principal,
+) -> Result<Json<Vec<Session>>, AppError> {
+    let sessions = repo
+        .list_sessions(principal.user_id, principal.session_id()?)
+        .await?;
+
+    Ok(Json(sessions))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Session to revoke, which may be the current one."),
+    ),
+    responses(
+        (status = 204, description = "Session revoked, its tokens no longer work."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 404, body = ProblemDetails, description = "No such live session of the user."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn revoke_session(
+    Extension(repo): Extension<AuthRepoExt>,
+    principal: Principal,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    principal.session_id()?;
+
+    if !repo
+        .revoke_user_session(principal.user_id, session_id)
+        .await?
+    {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password-reset/request",
+    responses(
+        (status = 202, description = "A reset token is emailed if a verified account exists for the address."),
+        (status = 429, body = ProblemDetails, description = "Too many requests, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = PasswordResetRequest,
+    tag = "auth",
+)]
+async fn request_password_reset(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<PasswordResetRequest>,
+) -> Result<StatusCode, AppError> {
+    state
+        .rate_limiter
+        .check(
+            Route::PasswordReset,
+            client.ip_address.as_deref(),
+            Some(&body.email),
+        )
+        .await?;
+
+    let config = state.config.clone();
+    let templates = state.templates.clone();
 
+    // Everything happens in the background so that neither the response nor
+    // its timing reveals whether the address belongs to an account.
     spawn(async move {
-        let message = MessageBuilder::new()
-            .from((config.mail_author, config.mail_email))
-            .to((user.username.as_str(), body.email.as_str()))
-            .subject("Welcome to Gossip App!")
-            .html_body(format!(r#"Your account has been verified."#,));
-
-        SmtpClientBuilder::new(config.mail_host.as_str(), config.mail_port)
-            .implicit_tls(config.mail_tls)
-            .credentials((config.mail_username.as_str(), config.mail_password.as_str()))
-            .connect()
+        let user = match repo.find_user_id_password_by_email(&body.email).await {
+            Ok(Some(user)) if user.is_verified => user,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::error!("Failed to look up user for password reset: {:?}", e);
+                return;
+            }
+        };
+
+        let reset_token = token::generate();
+        let expires_at =
+            OffsetDateTime::now_utc() + Duration::seconds(config.password_reset_ttl as i64);
+
+        let email = templates.render(
+            &Message::PasswordReset {
+                token: &reset_token,
+                link: config
+                    .password_reset_url
+                    .as_ref()
+                    .map(|url| link_with_token(url, &reset_token)),
+                expires_in_minutes: config.password_reset_ttl / 60,
+            },
+            user.locale.as_deref(),
+            &user.username,
+            &user.email,
+        );
+
+        let result = repo
+            .create_password_reset(user.id, &token::hash(&reset_token), expires_at, &[email])
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to create password reset: {:?}", e);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password-reset/confirm",
+    responses(
+        (status = 204, description = "Password changed, all sessions and personal access tokens revoked."),
+        (status = 400, body = ProblemDetails, description = "Invalid or expired reset token."),
+    ),
+    request_body = PasswordResetConfirmRequest,
+    tag = "auth",
+)]
+async fn confirm_password_reset(
+    Extension(repo): Extension<AuthRepoExt>,
+    Json(body): Json<PasswordResetConfirmRequest>,
+) -> Result<StatusCode, AppError> {
+    let password_hash = hash_password(&body.password)?;
+
+    repo.reset_password(&token::hash(&body.token), &password_hash)
+        .await?
+        .ok_or(AppError::InvalidResetToken)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    put,
+    path = "/auth/password",
+    responses(
+        (status = 200, body = LoginResponse, description = "Password changed. All other sessions and personal access tokens are revoked, and a new session is started."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Current password is incorrect, or a personal access token was used."),
+        (status = 429, body = ProblemDetails, description = "Too many requests or failed attempts, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = ChangePasswordRequest,
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn change_password(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    client: ClientInfo,
+    Json(body): Json<ChangePasswordRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let rate_limiter = &state.rate_limiter;
+
+    rate_limiter
+        .check(
+            Route::Login,
+            client.ip_address.as_deref(),
+            Some(&user.email),
+        )
+        .await?;
+    rate_limiter.check_lockout(&user.email).await?;
+
+    check_password(
+        rate_limiter,
+        &user.email,
+        &user.password_hash,
+        &body.current_password,
+    )
+    .await?;
+
+    let password_hash = hash_password(&body.new_password)?;
+
+    let email = state.templates.render(
+        &Message::PasswordChanged,
+        user.locale.as_deref(),
+        &user.username,
+        &user.email,
+    );
+
+    repo.change_password(user.id, &password_hash, &[email])
+        .await?;
+
+    let response = start_session(&repo, &state, user.id, &client).await?;
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login/2fa",
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 400, body = ProblemDetails, description = "Invalid code."),
+        (status = 401, body = ProblemDetails, description = "Invalid, expired or exhausted challenge token, log in again."),
+        (status = 429, body = ProblemDetails, description = "Too many requests or failed logins, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = TwoFactorLoginRequest,
+    tag = "auth",
+)]
+async fn login_two_factor(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<TwoFactorLoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let rate_limiter = &state.rate_limiter;
+
+    rate_limiter
+        .check(Route::Login, client.ip_address.as_deref(), None)
+        .await?;
+
+    let challenge_hash = token::hash(&body.challenge_token);
+
+    // Second factors are limited and locked out per account too, like
+    // passwords.
+    let email = repo
+        .get_login_challenge_email(&challenge_hash)
+        .await?
+        .ok_or(AppError::InvalidChallenge)?;
+
+    rate_limiter.check(Route::Login, None, Some(&email)).await?;
+    rate_limiter.check_lockout(&email).await?;
+
+    let user_id = repo
+        .record_login_challenge_attempt(&challenge_hash, state.config.login_challenge_max_attempts)
+        .await?
+        .ok_or(AppError::InvalidChallenge)?;
+
+    check_second_factor(&repo, rate_limiter, &email, user_id, &body.code).await?;
+
+    rate_limiter.clear_login_failures(&email).await?;
+
+    repo.consume_login_challenge(&challenge_hash)
+        .await?
+        .ok_or(AppError::InvalidChallenge)?;
+
+    let response = start_session(&repo, &state, user_id, &client).await?;
+
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/totp",
+    responses(
+        (status = 200, body = TotpEnrollment, description = "New secret, to be confirmed with a first code. Replaces any unconfirmed one."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 409, body = ProblemDetails, description = "Two-factor authentication is already enabled."),
+    ),
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn enroll_totp(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> Result<Json<TotpEnrollment>, AppError> {
+    let secret = totp::generate_secret();
+
+    if !repo.enroll_totp(user.id, &secret).await? {
+        return Err(AppError::TwoFactorEnabled);
+    }
+
+    Ok(Json(TotpEnrollment {
+        secret: totp::encode_secret(&secret),
+        otpauth_uri: totp::otpauth_uri(&secret, &state.config.totp_issuer, &user.email),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/totp/confirm",
+    responses(
+        (status = 200, body = RecoveryCodes, description = "Two-factor authentication enabled."),
+        (status = 400, body = ProblemDetails, description = "Invalid code."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 404, body = ProblemDetails, description = "No enrollment to confirm."),
+        (status = 409, body = ProblemDetails, description = "Two-factor authentication is already enabled."),
+    ),
+    request_body = ConfirmTotpRequest,
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn confirm_totp(
+    Extension(repo): Extension<AuthRepoExt>,
+    principal: Principal,
+    Json(body): Json<ConfirmTotpRequest>,
+) -> Result<Json<RecoveryCodes>, AppError> {
+    principal.session_id()?;
+
+    let authenticator = repo
+        .get_totp_authenticator(principal.user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    if authenticator.is_confirmed {
+        return Err(AppError::TwoFactorEnabled);
+    }
+
+    let step = totp::verify(&authenticator.secret, body.code.trim(), unix_time())
+        .ok_or(AppError::InvalidCode)?;
+
+    let recovery_codes = generate_recovery_codes();
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect::<Vec<_>>();
+
+    if !repo
+        .confirm_totp(principal.user_id, step as i64, &recovery_code_hashes)
+        .await?
+    {
+        return Err(AppError::TwoFactorEnabled);
+    }
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/2fa/disable",
+    responses(
+        (status = 204, description = "Authenticator and recovery codes removed."),
+        (status = 400, body = ProblemDetails, description = "Invalid code."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Password is incorrect, or a personal access token was used."),
+        (status = 429, body = ProblemDetails, description = "Too many requests or failed attempts, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = DisableTwoFactorRequest,
+    tag = "auth",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn disable_two_factor(
+    Extension(repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    client: ClientInfo,
+    Json(body): Json<DisableTwoFactorRequest>,
+) -> Result<StatusCode, AppError> {
+    let rate_limiter = &state.rate_limiter;
+
+    rate_limiter
+        .check(
+            Route::Login,
+            client.ip_address.as_deref(),
+            Some(&user.email),
+        )
+        .await?;
+    rate_limiter.check_lockout(&user.email).await?;
+
+    // A stolen access token alone must not be enough to turn it off.
+    check_password(
+        rate_limiter,
+        &user.email,
+        &user.password_hash,
+        &body.password,
+    )
+    .await?;
+    check_second_factor(&repo, rate_limiter, &user.email, user.id, &body.code).await?;
+
+    repo.disable_two_factor(user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    let hash = Argon2::default()
+        .hash_password(password.as_ref(), &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {e}"))?;
+
+    Ok(hash.to_string())
+}
+
+fn parse_password_hash(password_hash: &str) -> Result<PasswordHash<'_>, AppError> {
+    let hash =
+        PasswordHash::new(password_hash).map_err(|e| anyhow!("Invalid password hash: {e}"))?;
+
+    Ok(hash)
+}
+
+/// Checks the password of the account of `email`. Wrong passwords count
+/// toward its lockout, like failed logins.
+async fn check_password(
+    rate_limiter: &RateLimiter,
+    email: &str,
+    password_hash: &str,
+    password: &str,
+) -> Result<(), AppError> {
+    let hash = parse_password_hash(password_hash)?;
+
+    if Argon2::default()
+        .verify_password(password.as_ref(), &hash)
+        .is_err()
+    {
+        rate_limiter.record_login_failure(email).await?;
+        return Err(AppError::IncorrectPassword);
+    }
+
+    Ok(())
+}
+
+/// Checks and spends a second factor of the user, see `use_second_factor`.
+/// Wrong codes count toward the lockout of their account, like failed logins.
+async fn check_second_factor(
+    repo: &AuthRepoExt,
+    rate_limiter: &RateLimiter,
+    email: &str,
+    user_id: i32,
+    code: &str,
+) -> Result<(), AppError> {
+    if !use_second_factor(repo, user_id, code).await? {
+        rate_limiter.record_login_failure(email).await?;
+        return Err(AppError::InvalidCode);
+    }
+
+    Ok(())
+}
+
+/// Starts a session for a user who proved their identity, or a login challenge
+/// if they also have to pass a second factor.
+pub(crate) async fn finish_login(
+    repo: &AuthRepoExt,
+    state: &AppState,
+    user_id: i32,
+    client: &ClientInfo,
+) -> Result<Response, AppError> {
+    let two_factor_enabled = repo
+        .get_totp_authenticator(user_id)
+        .await?
+        .is_some_and(|authenticator| authenticator.is_confirmed);
+
+    if !two_factor_enabled {
+        let response = start_session(repo, state, user_id, client).await?;
+
+        return Ok(Json(response).into_response());
+    }
+
+    let config = &state.config;
+    let challenge_token = token::generate();
+    let expires_at =
+        OffsetDateTime::now_utc() + Duration::seconds(config.login_challenge_ttl as i64);
+
+    repo.create_login_challenge(&token::hash(&challenge_token), user_id, expires_at)
+        .await?;
+
+    let challenge = TwoFactorChallenge {
+        challenge_token,
+        expires_in: config.login_challenge_ttl,
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(challenge)).into_response())
+}
+
+/// Checks a code of the user's confirmed authenticator, or one of their
+/// recovery codes, and spends it so that it can't be used again.
+async fn use_second_factor(repo: &AuthRepoExt, user_id: i32, code: &str) -> Result<bool, AppError> {
+    let authenticator = repo.get_totp_authenticator(user_id).await?;
+
+    let Some(authenticator) = authenticator.filter(|authenticator| authenticator.is_confirmed)
+    else {
+        return Ok(false);
+    };
+
+    let code = code.trim();
+
+    if let Some(step) = totp::verify(&authenticator.secret, code, unix_time()) {
+        return repo.use_totp_step(user_id, step as i64).await;
+    }
+
+    repo.use_recovery_code(user_id, &hash_recovery_code(code))
+        .await
+}
+
+/// Ten codes of 10 random base32 characters, shown as `xxxxx-xxxxx`.
+fn generate_recovery_codes() -> Vec<String> {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    let mut rng = rand::rngs::OsRng;
+
+    (0..10)
+        .map(|_| {
+            let code = (0..10)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect::<String>();
+
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Hashes a recovery code the way it was typed, ignoring case, spaces and
+/// dashes.
+fn hash_recovery_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+
+    token::hash(&code)
+}
+
+/// Link to a page of the client app with `token` in its `token` query
+/// parameter, keeping any query the page already has.
+fn link_with_token(url: &Url, token: &str) -> String {
+    let mut url = url.clone();
+    url.query_pairs_mut().append_pair("token", token);
+
+    url.into()
+}
+
+fn unix_time() -> u64 {
+    OffsetDateTime::now_utc().unix_timestamp() as u64
+}
+
+/// Creates a new session for the user and issues its first token pair.
+async fn start_session(
+    repo: &AuthRepoExt,
+    state: &AppState,
+    user_id: i32,
+    client: &ClientInfo,
+) -> Result<LoginResponse, AppError> {
+    let config = &state.config;
+    let refresh_token = token::generate();
+
+    let session_id = repo
+        .create_session(
+            user_id,
+            client,
+            &token::hash(&refresh_token),
+            refresh_token_expiry(config),
+        )
+        .await?;
+
+    Ok(LoginResponse {
+        token: access_token(state, user_id, session_id)?,
+        refresh_token,
+        expires_in: config.access_token_ttl,
+    })
+}
+
+fn access_token(state: &AppState, user_id: i32, session_id: Uuid) -> Result<String, AppError> {
+    let config = &state.config;
+    let claims = TokenClaims::new(
+        user_id,
+        session_id,
+        &config.jwt_issuer,
+        &config.jwt_audience,
+        jwt::now(),
+        config.access_token_ttl,
+    );
+    let token = jwt::encode(&claims, &state.jwt_keys).context("Failed to sign access token")?;
+
+    Ok(token)
+}
+
+fn refresh_token_expiry(config: &Config) -> OffsetDateTime {
+    OffsetDateTime::now_utc() + Duration::seconds(config.refresh_token_ttl as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        config::{RateLimit, RateLimits},
+        features::auth::repositories::AuthRepo,
+        rate_limit::{Lockout, MemoryStore},
+    };
+
+    #[sqlx::test]
+    async fn test_wrong_second_factors_lock_the_account_out(pool: PgPool) {
+        let repo = Arc::new(AuthRepo { db: pool.clone() });
+        let limit = RateLimit {
+            requests: 100,
+            seconds: 60,
+        };
+        let rate_limiter = RateLimiter::new(
+            Arc::new(MemoryStore::default()),
+            RateLimits {
+                login: limit,
+                register: limit,
+                verify: limit,
+                password_reset: limit,
+                email_login: limit,
+            },
+            Lockout {
+                threshold: 3,
+                seconds: 60,
+            },
+        );
+
+        let user_id = repo
+            .create_user("a.b@c.com", "abc", "me", "123456", None, &[])
             .await
             .unwrap()
-            .send(message)
+            .unwrap();
+        let secret = totp::generate_secret();
+        repo.enroll_totp(user_id, &secret).await.unwrap();
+        repo.confirm_totp(user_id, 1, &[hash_recovery_code("aaaaa-bbbbb")])
             .await
             .unwrap();
-    });
 
-    return Ok(Json(LoginResponse {
-        token: jwt::encode(pending_verification.user_id, config.jwt_secret.as_ref())
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-    }));
+        for _ in 0..3 {
+            rate_limiter.check_lockout("a.b@c.com").await.unwrap();
+
+            let result =
+                check_second_factor(&repo, &rate_limiter, "a.b@c.com", user_id, "wrong-codes")
+                    .await;
+            assert!(matches!(result, Err(AppError::InvalidCode)));
+        }
+
+        let response = rate_limiter
+            .check_lockout("a.b@c.com")
+            .await
+            .unwrap_err()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Recovery codes work once, however they are typed.
+        check_second_factor(&repo, &rate_limiter, "a.b@c.com", user_id, "AAAAA-BBBBB")
+            .await
+            .unwrap();
+        let result =
+            check_second_factor(&repo, &rate_limiter, "a.b@c.com", user_id, "aaaaa-bbbbb").await;
+        assert!(matches!(result, Err(AppError::InvalidCode)));
+    }
 }
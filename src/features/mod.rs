@@ -1,2 +1,5 @@
+pub mod access_tokens;
 pub mod auth;
+pub mod oidc;
+pub mod outbox;
 pub mod users;
@@ -0,0 +1,239 @@
+//! A minimal OpenID Connect provider, served on a random local port for the
+//! tests to log in against.
+
+use std::{
+    collections::HashMap,
+    fs,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router, Server,
+};
+use reqwest::{redirect::Policy, Url};
+use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+use uuid::Uuid;
+
+use crate::{config::OidcProvider, jwt, jwt::JwtKeys, token};
+
+use super::{client, models::CallbackQuery};
+
+/// Who the user logging in at the mock provider is.
+#[derive(Debug, Clone)]
+pub struct MockIdentity {
+    pub sub: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub name: String,
+}
+
+#[derive(Debug)]
+struct Authorization {
+    nonce: String,
+    code_challenge: String,
+    redirect_uri: String,
+}
+
+struct MockState {
+    url: String,
+    keys: JwtKeys,
+    identity: Mutex<MockIdentity>,
+    codes: Mutex<HashMap<String, Authorization>>,
+}
+
+pub struct MockIssuer {
+    state: Arc<MockState>,
+}
+
+const CLIENT_ID: &str = "gossip";
+const CLIENT_SECRET: &str = "s3cr3t";
+
+impl MockIssuer {
+    pub async fn start(identity: MockIdentity) -> MockIssuer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let state = Arc::new(MockState {
+            url,
+            keys: signing_keys(),
+            identity: Mutex::new(identity),
+            codes: Mutex::new(HashMap::new()),
+        });
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/authorize", get(authorize))
+            .route("/token", post(exchange_code))
+            .with_state(state.clone());
+
+        tokio::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        MockIssuer { state }
+    }
+
+    /// The provider configuration of a client registered at the mock.
+    pub fn provider(&self) -> OidcProvider {
+        OidcProvider {
+            name: "mock".to_owned(),
+            issuer: self.state.url.clone(),
+            client_id: CLIENT_ID.to_owned(),
+            client_secret: CLIENT_SECRET.to_owned(),
+            redirect_url: "https://gossip.test/oidc/callback".to_owned(),
+        }
+    }
+
+    pub fn set_identity(&self, identity: MockIdentity) {
+        *self.state.identity.lock().unwrap() = identity;
+    }
+
+    /// Plays the user logging in at `authorization_url`, and returns the query
+    /// the provider redirects back with.
+    pub async fn authorize(&self, authorization_url: &str) -> CallbackQuery {
+        let http = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+
+        let response = http.get(authorization_url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let location = response.headers()["location"].to_str().unwrap();
+        let query = Url::parse(location).unwrap();
+        let param = |name: &str| {
+            query
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        CallbackQuery {
+            state: param("state").unwrap(),
+            code: param("code"),
+            error: param("error"),
+        }
+    }
+}
+
+fn signing_keys() -> JwtKeys {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let dir = std::env::temp_dir().join(format!("gossip-mock-oidc-{}", Uuid::new_v4()));
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("mock.pem"),
+        pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref())),
+    )
+    .unwrap();
+
+    let keys = JwtKeys::load(&dir, "mock").unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    keys
+}
+
+async fn discovery(State(state): State<Arc<MockState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "issuer": state.url,
+        "authorization_endpoint": format!("{}/authorize", state.url),
+        "token_endpoint": format!("{}/token", state.url),
+        "jwks_uri": format!("{}/jwks", state.url),
+    }))
+}
+
+async fn jwks(State(state): State<Arc<MockState>>) -> impl IntoResponse {
+    Json(state.keys.jwks().clone())
+}
+
+/// Logs the user in right away, as if they had entered their credentials.
+async fn authorize(
+    State(state): State<Arc<MockState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let valid = params.get("response_type").map(String::as_str) == Some("code")
+        && params.get("client_id").map(String::as_str) == Some(CLIENT_ID)
+        && params.get("code_challenge_method").map(String::as_str) == Some("S256")
+        && params
+            .get("scope")
+            .is_some_and(|scope| scope.split(' ').any(|scope| scope == "openid"));
+
+    let (Some(redirect_uri), Some(login_state)) = (params.get("redirect_uri"), params.get("state"))
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let mut location = Url::parse(redirect_uri).unwrap();
+    location.query_pairs_mut().append_pair("state", login_state);
+
+    if !valid {
+        location
+            .query_pairs_mut()
+            .append_pair("error", "invalid_request");
+        return Redirect::to(location.as_str()).into_response();
+    }
+
+    let code = token::generate();
+
+    state.codes.lock().unwrap().insert(
+        code.clone(),
+        Authorization {
+            nonce: params.get("nonce").cloned().unwrap_or_default(),
+            code_challenge: params.get("code_challenge").cloned().unwrap_or_default(),
+            redirect_uri: redirect_uri.clone(),
+        },
+    );
+
+    location.query_pairs_mut().append_pair("code", &code);
+    Redirect::to(location.as_str()).into_response()
+}
+
+async fn exchange_code(
+    State(state): State<Arc<MockState>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    let authorization = state.codes.lock().unwrap().remove(param("code"));
+
+    let valid = authorization.as_ref().is_some_and(|authorization| {
+        param("grant_type") == "authorization_code"
+            && param("client_id") == CLIENT_ID
+            && param("client_secret") == CLIENT_SECRET
+            && param("redirect_uri") == authorization.redirect_uri
+            && client::code_challenge(param("code_verifier")) == authorization.code_challenge
+    });
+
+    let (true, Some(authorization)) = (valid, authorization) else {
+        let error = serde_json::json!({ "error": "invalid_grant" });
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    };
+
+    let identity = state.identity.lock().unwrap().clone();
+    let now = jwt::now();
+    let claims = serde_json::json!({
+        "iss": state.url,
+        "aud": CLIENT_ID,
+        "sub": identity.sub,
+        "email": identity.email,
+        "email_verified": identity.email_verified,
+        "name": identity.name,
+        "nonce": authorization.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+
+    Json(serde_json::json!({
+        "access_token": token::generate(),
+        "token_type": "Bearer",
+        "id_token": jwt::encode(&claims, &state.keys).unwrap(),
+    }))
+    .into_response()
+}
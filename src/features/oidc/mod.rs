@@ -0,0 +1,8 @@
+mod client;
+#[cfg(test)]
+mod mock;
+pub mod models;
+pub mod repositories;
+pub mod routes;
+
+pub use routes::router;
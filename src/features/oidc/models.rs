@@ -0,0 +1,33 @@
+use sqlx::FromRow;
+use utoipa::IntoParams;
+
+/// A login started at a provider, found again by the `state` of its callback.
+#[derive(Debug, FromRow)]
+pub struct OidcLogin {
+    /// PKCE secret, whose digest was sent to the provider as `code_challenge`.
+    pub code_verifier: String,
+    /// Value the ID token must carry in its `nonce` claim.
+    pub nonce: String,
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+pub struct CallbackQuery {
+    /// The `state` the login was started with.
+    pub state: String,
+    /// Authorization code, missing if the login failed or was cancelled.
+    pub code: Option<String>,
+    /// Error code of the provider, set instead of `code`.
+    pub error: Option<String>,
+}
+
+/// The claims of a verified ID token this service uses.
+#[derive(Debug, serde::Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    /// Whether the provider verified `email`, which is ignored otherwise.
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use axum::{async_trait, Extension};
+use time::OffsetDateTime;
+
+use crate::{db::Db, error::AppError};
+
+use super::models::OidcLogin;
+
+pub type OidcRepoExt = Extension<Arc<OidcRepo>>;
+
+pub struct OidcRepo {
+    pub db: Db,
+}
+
+#[async_trait]
+pub trait OidcRepoImpl {
+    async fn create_login(
+        &self,
+        state_hash: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), AppError>;
+
+    /// Deletes a live login of the provider. Returns it, or `None` if it is
+    /// unknown, expired or was already used.
+    async fn consume_login(
+        &self,
+        state_hash: &str,
+        provider: &str,
+    ) -> Result<Option<OidcLogin>, AppError>;
+
+    /// The user an external identity is linked to, if any.
+    async fn get_identity_user(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<i32>, AppError>;
+
+    /// Links an external identity to the account of its verified email,
+    /// creating the account if there is none, and returns the user.
+    ///
+    /// An unverified account of that email gets verified, and its password and
+    /// name replaced by `password_hash` and `name`.
+    async fn link_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+        email: &str,
+        password_hash: &str,
+        name: &str,
+    ) -> Result<i32, AppError>;
+}
+
+#[async_trait]
+impl OidcRepoImpl for OidcRepo {
+    async fn create_login(
+        &self,
+        state_hash: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), AppError> {
+        sqlx::query_file!(
+            "queries/oidc/create_login.sql",
+            state_hash,
+            provider,
+            code_verifier,
+            nonce,
+            expires_at
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn consume_login(
+        &self,
+        state_hash: &str,
+        provider: &str,
+    ) -> Result<Option<OidcLogin>, AppError> {
+        let login = sqlx::query_file_as!(
+            OidcLogin,
+            "queries/oidc/consume_login.sql",
+            state_hash,
+            provider
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(login)
+    }
+
+    async fn get_identity_user(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<i32>, AppError> {
+        let user_id =
+            sqlx::query_file_scalar!("queries/oidc/get_identity_user.sql", provider, subject)
+                .fetch_optional(&self.db)
+                .await?;
+
+        Ok(user_id)
+    }
+
+    async fn link_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+        email: &str,
+        password_hash: &str,
+        name: &str,
+    ) -> Result<i32, AppError> {
+        let user_id = sqlx::query_file_scalar!(
+            "queries/oidc/link_identity.sql",
+            provider,
+            subject,
+            email,
+            password_hash,
+            name
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(user_id)
+    }
+}
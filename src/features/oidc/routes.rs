@@ -0,0 +1,383 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    response::{Redirect, Response},
+    routing::get,
+    Extension, Router,
+};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    config::{Config, OidcProvider},
+    error::AppError,
+    extract::{Path, Query},
+    features::auth::{
+        self,
+        models::ClientInfo,
+        repositories::{AuthRepo, AuthRepoExt},
+    },
+    state::AppState,
+    token,
+};
+
+use super::{
+    client,
+    models::CallbackQuery,
+    repositories::{OidcRepo, OidcRepoExt, OidcRepoImpl},
+};
+
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/:provider/start", get(start))
+        .route("/:provider/callback", get(callback))
+        .layer(Extension(Arc::new(OidcRepo {
+            db: state.db.clone(),
+        })))
+        .layer(Extension(Arc::new(AuthRepo {
+            db: state.db.clone(),
+        })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/{provider}/start",
+    responses(
+        (status = 303, description = "Redirect to the login page of the provider."),
+        (status = 401, body = ProblemDetails, description = "The provider can't be reached."),
+        (status = 404, body = ProblemDetails, description = "Unknown provider."),
+    ),
+    tag = "auth",
+)]
+async fn start(
+    Path(provider): Path<String>,
+    Extension(repo): OidcRepoExt,
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, AppError> {
+    let config = &state.config;
+    let provider = find_provider(config, &provider)?;
+
+    let url = begin_login(&repo, &state.http, provider, config.oidc_login_ttl).await?;
+
+    Ok(Redirect::to(&url))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/{provider}/callback",
+    params(CallbackQuery),
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 202, body = TwoFactorChallenge, description = "Identity verified, a second factor must be sent to `/auth/login/2fa`."),
+        (status = 401, body = ProblemDetails, description = "The login failed, was cancelled or expired."),
+        (status = 403, body = ProblemDetails, description = "The provider didn't verify the email address of a new identity."),
+        (status = 404, body = ProblemDetails, description = "Unknown provider."),
+    ),
+    tag = "auth",
+)]
+async fn callback(
+    Path(provider): Path<String>,
+    Query(query): Query<CallbackQuery>,
+    Extension(repo): OidcRepoExt,
+    Extension(auth_repo): Extension<AuthRepoExt>,
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+) -> Result<Response, AppError> {
+    let provider = find_provider(&state.config, &provider)?;
+
+    let user_id = complete_login(&repo, &state.http, provider, query).await?;
+
+    auth::routes::finish_login(&auth_repo, &state, user_id, &client).await
+}
+
+fn find_provider<'a>(config: &'a Config, name: &str) -> Result<&'a OidcProvider, AppError> {
+    config
+        .oidc_providers
+        .iter()
+        .find(|provider| provider.name == name)
+        .ok_or(AppError::NotFound)
+}
+
+/// Records a new login and returns the URL of the provider to send the user to.
+async fn begin_login(
+    repo: &OidcRepo,
+    http: &reqwest::Client,
+    provider: &OidcProvider,
+    ttl_seconds: u64,
+) -> Result<String, AppError> {
+    let metadata = client::discover(http, provider)
+        .await
+        .map_err(login_failed)?;
+
+    let state = token::generate();
+    let nonce = token::generate();
+    let code_verifier = token::generate();
+    let expires_at = OffsetDateTime::now_utc() + Duration::seconds(ttl_seconds as i64);
+
+    repo.create_login(
+        &token::hash(&state),
+        &provider.name,
+        &code_verifier,
+        &nonce,
+        expires_at,
+    )
+    .await?;
+
+    let url = client::authorization_url(&metadata, provider, &state, &nonce, &code_verifier)
+        .map_err(login_failed)?;
+
+    Ok(url)
+}
+
+/// Verifies the identity the provider called back with, and returns the user
+/// it is linked to, linking it first if needed.
+async fn complete_login(
+    repo: &OidcRepo,
+    http: &reqwest::Client,
+    provider: &OidcProvider,
+    query: CallbackQuery,
+) -> Result<i32, AppError> {
+    let login = repo
+        .consume_login(&token::hash(&query.state), &provider.name)
+        .await?
+        .ok_or(AppError::OidcLoginFailed)?;
+
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            tracing::info!("{} login failed: {:?}", provider.name, error);
+            return Err(AppError::OidcLoginFailed);
+        }
+    };
+
+    let metadata = client::discover(http, provider)
+        .await
+        .map_err(login_failed)?;
+
+    let claims = client::authenticate(
+        http,
+        &metadata,
+        provider,
+        &code,
+        &login.code_verifier,
+        &login.nonce,
+    )
+    .await
+    .map_err(login_failed)?;
+
+    if let Some(user_id) = repo.get_identity_user(&provider.name, &claims.sub).await? {
+        return Ok(user_id);
+    }
+
+    // Identities are only linked by an email the provider vouches for.
+    let Some(email) = claims.email.filter(|_| claims.email_verified) else {
+        return Err(AppError::EmailNotVerified);
+    };
+
+    let name = claims
+        .name
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_owned());
+
+    // New accounts get a password nobody knows, until one is set by a reset.
+    let password_hash = auth::routes::hash_password(&token::generate())?;
+
+    repo.link_identity(&provider.name, &claims.sub, &email, &password_hash, &name)
+        .await
+}
+
+fn login_failed(e: anyhow::Error) -> AppError {
+    tracing::warn!("OpenID Connect login failed: {:#}", e);
+
+    AppError::OidcLoginFailed
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::features::{
+        auth::repositories::AuthRepoImpl,
+        oidc::mock::{MockIdentity, MockIssuer},
+    };
+
+    fn identity(sub: &str, email: &str, email_verified: bool) -> MockIdentity {
+        MockIdentity {
+            sub: sub.to_owned(),
+            email: email.to_owned(),
+            email_verified,
+            name: "Ann".to_owned(),
+        }
+    }
+
+    async fn log_in(
+        repo: &OidcRepo,
+        issuer: &MockIssuer,
+        provider: &OidcProvider,
+    ) -> Result<i32, AppError> {
+        let http = reqwest::Client::new();
+
+        let url = begin_login(repo, &http, provider, 600).await?;
+        let query = issuer.authorize(&url).await;
+
+        complete_login(repo, &http, provider, query).await
+    }
+
+    #[sqlx::test]
+    async fn test_creates_verified_account(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let auth_repo = AuthRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+        let provider = issuer.provider();
+
+        let user_id = log_in(&repo, &issuer, &provider).await.unwrap();
+
+        let user = auth_repo
+            .find_user_id_password_by_email("ann@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.id, user_id);
+        assert_eq!(user.username, "Ann");
+        assert!(user.is_verified);
+
+        // The identity stays linked when its email changes.
+        issuer.set_identity(identity("ann-1", "ann@elsewhere.com", false));
+        assert_eq!(log_in(&repo, &issuer, &provider).await.unwrap(), user_id);
+    }
+
+    #[sqlx::test]
+    async fn test_links_account_by_verified_email(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let auth_repo = AuthRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+        let provider = issuer.provider();
+
+        let user_id = auth_repo
+            .create_user("ann@c.com", "hash", "Annie", "123456", None, &[])
+            .await
+            .unwrap()
+            .unwrap();
+        auth_repo
+            .verify_email("ann@c.com", &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(log_in(&repo, &issuer, &provider).await.unwrap(), user_id);
+
+        // A verified account keeps its password and name.
+        let user = auth_repo
+            .find_user_id_password_by_email("ann@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.password_hash, "hash");
+        assert_eq!(user.username, "Annie");
+    }
+
+    #[sqlx::test]
+    async fn test_takes_over_unverified_account(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let auth_repo = AuthRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+        let provider = issuer.provider();
+
+        let user_id = auth_repo
+            .create_user("ann@c.com", "squatter", "Mallory", "123456", None, &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(log_in(&repo, &issuer, &provider).await.unwrap(), user_id);
+
+        let user = auth_repo
+            .find_user_id_password_by_email("ann@c.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.is_verified);
+        assert_ne!(user.password_hash, "squatter");
+        assert_eq!(user.username, "Ann");
+        assert!(auth_repo
+            .get_pending_verification("ann@c.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_unverified_email(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let auth_repo = AuthRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", false)).await;
+
+        let result = log_in(&repo, &issuer, &issuer.provider()).await;
+
+        assert!(matches!(result, Err(AppError::EmailNotVerified)));
+        assert!(auth_repo
+            .find_user_id_password_by_email("ann@c.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_state_is_single_use(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+        let provider = issuer.provider();
+        let http = reqwest::Client::new();
+
+        let url = begin_login(&repo, &http, &provider, 600).await.unwrap();
+        let query = issuer.authorize(&url).await;
+        let replayed = CallbackQuery {
+            state: query.state.clone(),
+            code: query.code.clone(),
+            error: None,
+        };
+
+        complete_login(&repo, &http, &provider, query)
+            .await
+            .unwrap();
+
+        let result = complete_login(&repo, &http, &provider, replayed).await;
+        assert!(matches!(result, Err(AppError::OidcLoginFailed)));
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_unknown_client(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+
+        let wrong_secret = OidcProvider {
+            client_secret: "guess".to_owned(),
+            ..issuer.provider()
+        };
+        let result = log_in(&repo, &issuer, &wrong_secret).await;
+        assert!(matches!(result, Err(AppError::OidcLoginFailed)));
+
+        // The provider redirects back with an error instead of a code.
+        let wrong_id = OidcProvider {
+            client_id: "other".to_owned(),
+            ..issuer.provider()
+        };
+        let result = log_in(&repo, &issuer, &wrong_id).await;
+        assert!(matches!(result, Err(AppError::OidcLoginFailed)));
+    }
+
+    #[sqlx::test]
+    async fn test_rejects_other_issuer(pool: PgPool) {
+        let repo = OidcRepo { db: pool.clone() };
+        let issuer = MockIssuer::start(identity("ann-1", "ann@c.com", true)).await;
+
+        // The discovery document doesn't match the configured issuer.
+        let provider = OidcProvider {
+            issuer: format!("{}/", issuer.provider().issuer),
+            ..issuer.provider()
+        };
+        let result = begin_login(&repo, &reqwest::Client::new(), &provider, 600).await;
+
+        assert!(matches!(result, Err(AppError::OidcLoginFailed)));
+    }
+}
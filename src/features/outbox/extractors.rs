@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use subtle::ConstantTimeEq;
+
+use crate::{error::AppError, features::auth::extractors::bearer_token, state::AppState};
+
+use super::models::OpsAccess;
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for OpsAccess {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, AppError> {
+        // Operator endpoints don't exist unless a token is configured.
+        let expected = state.config.ops_token.as_ref().ok_or(AppError::NotFound)?;
+
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+
+        if !bool::from(token.as_bytes().ct_eq(expected.as_bytes())) {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(OpsAccess)
+    }
+}
@@ -0,0 +1,7 @@
+mod extractors;
+pub mod models;
+pub mod repositories;
+pub mod routes;
+pub mod worker;
+
+pub use routes::router;
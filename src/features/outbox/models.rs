@@ -0,0 +1,65 @@
+use serde::Serialize;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::mail::Email;
+
+/// An email claimed from the outbox for delivery.
+#[derive(Debug, FromRow)]
+pub struct OutboxEmail {
+    pub id: i64,
+    pub to_name: String,
+    pub to_address: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    /// Delivery attempts made before this one.
+    pub attempts: i32,
+}
+
+impl From<OutboxEmail> for Email {
+    fn from(email: OutboxEmail) -> Email {
+        Email::new(
+            email.to_name,
+            email.to_address,
+            email.subject,
+            email.html_body,
+            email.text_body,
+        )
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema, FromRow)]
+pub struct OutboxStatus {
+    pub pending: i64,
+    pub sent: i64,
+    pub dead: i64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub oldest_pending_at: Option<OffsetDateTime>,
+}
+
+/// An email that exhausted its delivery attempts.
+#[derive(Debug, Serialize, ToSchema, FromRow)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub to_address: String,
+    pub subject: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OutboxReport {
+    pub status: OutboxStatus,
+    /// The most recent dead letters.
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+/// Proof that the request carries the operator token.
+#[derive(Debug)]
+pub struct OpsAccess;
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use axum::{async_trait, Extension};
+use sqlx::PgConnection;
+
+use crate::{db::Db, error::AppError, mail::Email};
+
+use super::models::{DeadLetter, OutboxEmail, OutboxStatus};
+
+pub type OutboxRepoExt = Extension<Arc<OutboxRepo>>;
+
+/// Queues emails for delivery by the outbox worker. Meant to be called inside
+/// the transaction of the write that triggers the emails, so that they are
+/// sent if and only if the write commits.
+pub async fn enqueue(conn: &mut PgConnection, emails: &[Email]) -> sqlx::Result<()> {
+    for email in emails {
+        sqlx::query_file!(
+            "queries/outbox/enqueue.sql",
+            email.to_name,
+            email.to_address,
+            email.subject,
+            email.html_body,
+            email.text_body
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub struct OutboxRepo {
+    pub db: Db,
+}
+
+#[async_trait]
+pub trait OutboxRepoImpl {
+    /// Claims up to `limit` due emails, hiding them from other workers for
+    /// `lease_seconds`.
+    async fn claim_due(&self, limit: i64, lease_seconds: f64)
+        -> Result<Vec<OutboxEmail>, AppError>;
+
+    async fn mark_sent(&self, id: i64) -> Result<(), AppError>;
+
+    /// Records a failed attempt. The email is retried after `retry_in_seconds`,
+    /// or marked dead once `max_attempts` is reached. Returns `true` if dead.
+    async fn mark_failed(
+        &self,
+        id: i64,
+        error: &str,
+        max_attempts: i32,
+        retry_in_seconds: f64,
+    ) -> Result<bool, AppError>;
+
+    async fn get_status(&self) -> Result<OutboxStatus, AppError>;
+
+    async fn get_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetter>, AppError>;
+
+    /// Puts a dead email back in the queue. Returns `false` if there is no
+    /// such dead email.
+    async fn retry_dead_letter(&self, id: i64) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+impl OutboxRepoImpl for OutboxRepo {
+    async fn claim_due(
+        &self,
+        limit: i64,
+        lease_seconds: f64,
+    ) -> Result<Vec<OutboxEmail>, AppError> {
+        let emails = sqlx::query_file_as!(
+            OutboxEmail,
+            "queries/outbox/claim_due.sql",
+            limit,
+            lease_seconds
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(emails)
+    }
+
+    async fn mark_sent(&self, id: i64) -> Result<(), AppError> {
+        sqlx::query_file!("queries/outbox/mark_sent.sql", id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: i64,
+        error: &str,
+        max_attempts: i32,
+        retry_in_seconds: f64,
+    ) -> Result<bool, AppError> {
+        let status = sqlx::query_file_scalar!(
+            "queries/outbox/mark_failed.sql",
+            id,
+            error,
+            max_attempts,
+            retry_in_seconds
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(status == "dead")
+    }
+
+    async fn get_status(&self) -> Result<OutboxStatus, AppError> {
+        let status = sqlx::query_file_as!(OutboxStatus, "queries/outbox/get_status_counts.sql")
+            .fetch_one(&self.db)
+            .await?;
+
+        Ok(status)
+    }
+
+    async fn get_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetter>, AppError> {
+        let dead_letters =
+            sqlx::query_file_as!(DeadLetter, "queries/outbox/get_dead_letters.sql", limit)
+                .fetch_all(&self.db)
+                .await?;
+
+        Ok(dead_letters)
+    }
+
+    async fn retry_dead_letter(&self, id: i64) -> Result<bool, AppError> {
+        let retried = sqlx::query_file_scalar!("queries/outbox/retry_dead_letter.sql", id)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(retried.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_claimed_emails_are_leased(pool: PgPool) {
+        let repo = OutboxRepo { db: pool.clone() };
+
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(
+            &mut conn,
+            &[
+                Email::new("me", "a.b@c.com", "First", "1", "1"),
+                Email::new("you", "d.e@f.com", "Second", "2", "2"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let claimed = repo.claim_due(1, 60.0).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].subject, "First");
+        assert_eq!(claimed[0].attempts, 0);
+
+        let claimed = repo.claim_due(10, 60.0).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].subject, "Second");
+
+        assert!(repo.claim_due(10, 60.0).await.unwrap().is_empty());
+
+        repo.mark_sent(claimed[0].id).await.unwrap();
+
+        let status = repo.get_status().await.unwrap();
+        assert_eq!((status.pending, status.sent, status.dead), (1, 1, 0));
+    }
+
+    #[sqlx::test]
+    async fn test_failed_emails_are_retried_then_dead_lettered(pool: PgPool) {
+        let repo = OutboxRepo { db: pool.clone() };
+
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(
+            &mut conn,
+            &[Email::new("me", "a.b@c.com", "Hello", "Hi", "Hi")],
+        )
+        .await
+        .unwrap();
+
+        let id = repo.claim_due(10, 60.0).await.unwrap()[0].id;
+
+        // Retry immediately so that the email is due again.
+        assert!(!repo
+            .mark_failed(id, "connection refused", 2, 0.0)
+            .await
+            .unwrap());
+
+        let claimed = repo.claim_due(10, 60.0).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].attempts, 1);
+
+        assert!(repo
+            .mark_failed(id, "connection reset", 2, 0.0)
+            .await
+            .unwrap());
+        assert!(repo.claim_due(10, 60.0).await.unwrap().is_empty());
+
+        let dead_letters = repo.get_dead_letters(10).await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert_eq!(
+            dead_letters[0].last_error.as_deref(),
+            Some("connection reset")
+        );
+
+        assert!(repo.retry_dead_letter(id).await.unwrap());
+        assert!(!repo.retry_dead_letter(id).await.unwrap());
+        assert_eq!(repo.claim_due(10, 60.0).await.unwrap()[0].attempts, 0);
+    }
+}
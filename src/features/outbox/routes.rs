@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Router,
+};
+
+use crate::{
+    error::AppError,
+    extract::{Json, Path},
+    state::AppState,
+};
+
+use super::{
+    models::{OpsAccess, OutboxReport},
+    repositories::{OutboxRepo, OutboxRepoExt, OutboxRepoImpl},
+};
+
+const DEAD_LETTER_LIMIT: i64 = 50;
+
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/outbox", get(outbox_report))
+        .route("/outbox/:id/retry", post(retry_dead_letter))
+        .layer(Extension(Arc::new(OutboxRepo {
+            db: state.db.clone(),
+        })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ops/outbox",
+    responses(
+        (status = 200, body = OutboxReport),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+    ),
+    tag = "ops",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn outbox_report(
+    _: OpsAccess,
+    Extension(repo): OutboxRepoExt,
+) -> Result<Json<OutboxReport>, AppError> {
+    Ok(Json(OutboxReport {
+        status: repo.get_status().await?,
+        dead_letters: repo.get_dead_letters(DEAD_LETTER_LIMIT).await?,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/ops/outbox/{id}/retry",
+    responses(
+        (status = 204, description = "Email queued for delivery again."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 404, body = ProblemDetails, description = "No dead email with this ID."),
+    ),
+    tag = "ops",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn retry_dead_letter(
+    _: OpsAccess,
+    Path(id): Path<i64>,
+    Extension(repo): OutboxRepoExt,
+) -> Result<StatusCode, AppError> {
+    if repo.retry_dead_letter(id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound)
+    }
+}
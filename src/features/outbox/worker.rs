@@ -0,0 +1,189 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{error::AppError, mail::Mailer, state::AppState};
+
+use super::repositories::{OutboxRepo, OutboxRepoImpl};
+
+const BATCH_SIZE: i64 = 20;
+
+/// How long a claimed email stays hidden from other workers.
+const LEASE_SECONDS: f64 = 5.0 * 60.0;
+
+/// Upper bound for the delay between two attempts.
+const MAX_RETRY_DELAY_SECONDS: f64 = 6.0 * 60.0 * 60.0;
+
+/// Delivers queued emails until the process exits.
+pub async fn run(state: Arc<AppState>) {
+    let config = &state.config;
+    let repo = Arc::new(OutboxRepo {
+        db: state.db.clone(),
+    });
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.outbox_poll_interval));
+
+    loop {
+        interval.tick().await;
+
+        // Run every round in its own task so that a panic, e.g. in a mailer,
+        // doesn't stop the worker.
+        let round = tokio::spawn(deliver_due(
+            repo.clone(),
+            state.mailer.clone(),
+            config.outbox_max_attempts,
+            config.outbox_retry_base,
+        ));
+
+        match round.await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::error!("Outbox delivery round failed: {:?}", e),
+            Err(e) => tracing::error!("Outbox delivery round panicked: {}", e),
+        }
+    }
+}
+
+/// Sends every email that is due. Returns the number of emails sent.
+pub async fn deliver_due(
+    repo: Arc<impl OutboxRepoImpl>,
+    mailer: Arc<dyn Mailer>,
+    max_attempts: i32,
+    retry_base: u64,
+) -> Result<usize, AppError> {
+    let mut sent = 0;
+
+    loop {
+        let batch = repo.claim_due(BATCH_SIZE, LEASE_SECONDS).await?;
+        let batch_size = batch.len() as i64;
+
+        for email in batch {
+            let id = email.id;
+            let attempts = email.attempts;
+
+            match mailer.send(email.into()).await {
+                Ok(()) => {
+                    repo.mark_sent(id).await?;
+                    sent += 1;
+                }
+                Err(e) => {
+                    let retry_in = retry_delay(retry_base, attempts);
+                    let dead = repo
+                        .mark_failed(id, &e.to_string(), max_attempts, retry_in)
+                        .await?;
+
+                    if dead {
+                        tracing::error!(
+                            id,
+                            "Giving up on email after {} attempts: {}",
+                            attempts + 1,
+                            e
+                        );
+                    } else {
+                        tracing::warn!(
+                            id,
+                            "Failed to send email, retrying in {}s: {}",
+                            retry_in,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        if batch_size < BATCH_SIZE {
+            return Ok(sent);
+        }
+    }
+}
+
+/// Exponential backoff: `retry_base` seconds after the first failure, doubling
+/// with every further one.
+fn retry_delay(retry_base: u64, attempts: i32) -> f64 {
+    let delay = retry_base as f64 * 2f64.powi(attempts);
+
+    delay.min(MAX_RETRY_DELAY_SECONDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::async_trait;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        features::outbox::repositories::enqueue,
+        mail::{Email, MemoryMailer},
+    };
+
+    #[derive(Debug)]
+    struct FailingMailer;
+
+    #[async_trait]
+    impl Mailer for FailingMailer {
+        async fn send(&self, _email: Email) -> anyhow::Result<()> {
+            anyhow::bail!("connection refused")
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_a_cap() {
+        assert_eq!(retry_delay(30, 0), 30.0);
+        assert_eq!(retry_delay(30, 1), 60.0);
+        assert_eq!(retry_delay(30, 4), 480.0);
+        assert_eq!(retry_delay(30, 20), MAX_RETRY_DELAY_SECONDS);
+    }
+
+    #[sqlx::test]
+    async fn test_deliver_due(pool: PgPool) {
+        let repo = Arc::new(OutboxRepo { db: pool.clone() });
+        let mailer = Arc::new(MemoryMailer::default());
+
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(
+            &mut conn,
+            &[Email::new("me", "a.b@c.com", "Hello", "Hi", "Hi")],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            deliver_due(repo.clone(), mailer.clone(), 3, 30)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(mailer.sent()[0].subject, "Hello");
+
+        assert_eq!(
+            deliver_due(repo.clone(), mailer.clone(), 3, 30)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(mailer.sent().len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn test_deliver_due_schedules_retry(pool: PgPool) {
+        let repo = Arc::new(OutboxRepo { db: pool.clone() });
+
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(
+            &mut conn,
+            &[Email::new("me", "a.b@c.com", "Hello", "Hi", "Hi")],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            deliver_due(repo.clone(), Arc::new(FailingMailer), 3, 30)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let status = repo.get_status().await.unwrap();
+        assert_eq!(status.pending, 1);
+
+        // Not due again until the backoff has passed.
+        assert!(repo.claim_due(10, 60.0).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+//! Decoding of uploaded profile images and re-encoding into the sizes they are
+//! served in.
+
+use std::io::Cursor;
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, metadata::Orientation, DynamicImage,
+    ImageDecoder, ImageError, ImageFormat, ImageReader, Limits, Rgb, RgbImage,
+};
+
+use crate::error::AppError;
+
+/// Largest width or height of an upload, beyond which it isn't decoded.
+const MAX_DIMENSION: u32 = 8000;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Avatar,
+    Banner,
+}
+
+impl ImageKind {
+    /// Name of the kind in blob keys, in the plural.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageKind::Avatar => "avatars",
+            ImageKind::Banner => "banners",
+        }
+    }
+
+    /// Widths and heights the images are stored in, largest first. They
+    /// share an aspect ratio, and the smallest is the smallest upload.
+    fn sizes(&self) -> &'static [(u32, u32)] {
+        match self {
+            ImageKind::Avatar => &[(512, 512), (256, 256), (64, 64)],
+            ImageKind::Banner => &[(1500, 500), (600, 200)],
+        }
+    }
+}
+
+/// One of the sizes of an image, as a JPEG.
+#[derive(Debug)]
+pub struct EncodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Decodes a JPEG, PNG, GIF or WebP image, told apart by its content rather
+/// than its declared type, and re-encodes it as a JPEG in every size of
+/// `kind`, cropped around the center to fit. Transparency is flattened onto
+/// white. The EXIF orientation is applied, and metadata isn't carried over.
+pub fn process(kind: ImageKind, data: &[u8]) -> Result<Vec<EncodedImage>, AppError> {
+    let image = decode(data)?;
+
+    let &(min_width, min_height) = kind.sizes().last().unwrap();
+
+    if image.width() < min_width || image.height() < min_height {
+        return Err(AppError::InvalidRequest(format!(
+            "The image must be at least {min_width}x{min_height} pixels."
+        )));
+    }
+
+    let image = crop_to_aspect_ratio(flatten(image), kind.sizes()[0]);
+
+    kind.sizes()
+        .iter()
+        .map(|&(width, height)| {
+            let resized = image::imageops::resize(&image, width, height, FilterType::Lanczos3);
+
+            let mut data = Vec::new();
+            JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)
+                .encode_image(&resized)
+                .map_err(|e| AppError::Internal(e.into()))?;
+
+            Ok(EncodedImage {
+                width,
+                height,
+                data,
+            })
+        })
+        .collect()
+}
+
+fn decode(data: &[u8]) -> Result<DynamicImage, AppError> {
+    let unsupported =
+        || AppError::InvalidRequest("The file is not a JPEG, PNG, GIF or WebP image.".to_owned());
+
+    let invalid = |e: ImageError| match e {
+        ImageError::Limits(_) => AppError::InvalidRequest(format!(
+            "The image must be at most {MAX_DIMENSION}x{MAX_DIMENSION} pixels."
+        )),
+        _ => unsupported(),
+    };
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| unsupported())?;
+
+    if !matches!(
+        reader.format(),
+        Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP)
+    ) {
+        return Err(unsupported());
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+fn flatten(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.into_rgb8();
+    }
+
+    let image = image.into_rgba8();
+
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// The largest part of `image` around its center with the aspect ratio of
+/// `width` to `height`.
+fn crop_to_aspect_ratio(image: RgbImage, (width, height): (u32, u32)) -> RgbImage {
+    let (image_width, image_height) = image.dimensions();
+
+    let (crop_width, crop_height) =
+        if image_width as u64 * height as u64 > image_height as u64 * width as u64 {
+            (
+                (image_height as u64 * width as u64 / height as u64) as u32,
+                image_height,
+            )
+        } else {
+            (
+                image_width,
+                (image_width as u64 * height as u64 / width as u64) as u32,
+            )
+        };
+
+    image::imageops::crop_imm(
+        &image,
+        (image_width - crop_width) / 2,
+        (image_height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+    .to_image()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageEncoder, RgbaImage};
+
+    use super::*;
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    #[test]
+    fn encodes_avatars_in_every_size() {
+        // A transparent PNG, wider than high.
+        let png = encode(
+            RgbaImage::from_pixel(300, 200, image::Rgba([0, 0, 0, 0])).into(),
+            ImageFormat::Png,
+        );
+
+        let images = process(ImageKind::Avatar, &png).unwrap();
+        let sizes = images
+            .iter()
+            .map(|image| (image.width, image.height))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(512, 512), (256, 256), (64, 64)]);
+
+        for encoded in images {
+            let image = image::load_from_memory(&encoded.data).unwrap();
+            assert_eq!(
+                image::guess_format(&encoded.data).unwrap(),
+                ImageFormat::Jpeg
+            );
+            assert_eq!(image.width(), encoded.width);
+            assert_eq!(image.height(), encoded.height);
+
+            // Flattened onto white.
+            assert!(image.to_rgb8().get_pixel(10, 10).0.iter().all(|&c| c > 250));
+        }
+    }
+
+    #[test]
+    fn crops_banners_around_the_center() {
+        // Black on the sides, white in the middle third.
+        let image = RgbImage::from_fn(900, 900, |x, _| {
+            if (300..600).contains(&x) {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+
+        let cropped = crop_to_aspect_ratio(image.clone(), (3, 1));
+        assert_eq!(cropped.dimensions(), (900, 300));
+
+        let cropped = crop_to_aspect_ratio(image, (1, 3));
+        assert_eq!(cropped.dimensions(), (300, 900));
+        assert!(cropped.pixels().all(|pixel| pixel.0 == [255, 255, 255]));
+    }
+
+    #[test]
+    fn applies_and_strips_exif_orientation() {
+        // Rotated 90° clockwise when shown, in big-endian TIFF.
+        let exif = [
+            0x4d, 0x4d, 0, 42, 0, 0, 0, 8, // Header
+            0, 1, // One entry
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // Orientation
+            0, 0, 0, 0, // No next IFD
+        ];
+
+        // Wide as stored, high once rotated.
+        let image = RgbImage::from_pixel(2000, 600, Rgb([0, 0, 0]));
+        let mut jpeg = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut jpeg);
+        encoder.set_exif_metadata(exif.to_vec()).unwrap();
+        encoder
+            .write_image(image.as_raw(), 2000, 600, image::ExtendedColorType::Rgb8)
+            .unwrap();
+
+        let image = decode(&jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (600, 2000));
+
+        let images = process(ImageKind::Avatar, &jpeg).unwrap();
+        for encoded in images {
+            let mut decoder = ImageReader::new(Cursor::new(&encoded.data))
+                .with_guessed_format()
+                .unwrap()
+                .into_decoder()
+                .unwrap();
+            assert!(decoder.exif_metadata().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_images() {
+        let result = process(ImageKind::Avatar, b"<svg></svg>");
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+
+        let result = process(ImageKind::Avatar, b"BM\x3a\0\0\0\0\0\0\0\x36\0\0\0");
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+
+        let small = encode(RgbImage::new(50, 50).into(), ImageFormat::Png);
+        let result = process(ImageKind::Avatar, &small);
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+
+        // Large enough for an avatar, but not a banner.
+        let png = encode(RgbImage::new(500, 500).into(), ImageFormat::Png);
+        assert!(process(ImageKind::Avatar, &png).is_ok());
+        let result = process(ImageKind::Banner, &png);
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+
+        // Truncated.
+        let result = process(ImageKind::Avatar, &png[..png.len() / 2]);
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+    }
+}
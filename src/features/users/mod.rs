@@ -1,6 +1,8 @@
 mod extractors;
+pub mod images;
 pub mod models;
 pub mod repositories;
 pub mod routes;
+mod validation;
 
 pub use routes::router;
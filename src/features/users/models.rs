@@ -1,10 +1,192 @@
-use serde::Serialize;
-use sqlx::FromRow;
-use utoipa::ToSchema;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug, Clone, Serialize, ToSchema, FromRow)]
 pub struct UserProfile {
     pub id: i32,
     pub username: String,
-    pub bio: String,
+    /// Unique public name, without the `@`. Unset until the user picks one.
+    pub handle: Option<String>,
+    /// Unset if the user hides it from the caller.
+    pub bio: Option<String>,
+    /// Square profile picture in each of its sizes, largest first.
+    #[schema(value_type = Option<Vec<ProfileImage>>)]
+    pub avatar: Option<Json<Vec<ProfileImage>>>,
+    /// Header image with a 3:1 aspect ratio in each of its sizes, largest
+    /// first.
+    #[schema(value_type = Option<Vec<ProfileImage>>)]
+    pub banner: Option<Json<Vec<ProfileImage>>>,
+    /// Last change of the profile.
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub updated_at: OffsetDateTime,
+}
+
+/// Who can see a part of a profile, besides the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "visibility", rename_all = "snake_case")]
+pub enum Visibility {
+    Everyone,
+    /// Callers with an access token.
+    SignedIn,
+    Nobody,
+}
+
+#[derive(Debug, Serialize, ToSchema, FromRow)]
+pub struct PrivacySettings {
+    /// Who can find the user with `/user/by-email/{email}`. Lookups they
+    /// can't make find nobody.
+    pub email_lookup: Visibility,
+    /// Who can see the bio of the user.
+    pub bio: Visibility,
+}
+
+/// Settings left out are kept as they are.
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdatePrivacyRequest {
+    pub email_lookup: Option<Visibility>,
+    pub bio: Option<Visibility>,
+}
+
+/// A JPEG of a profile image in one of its sizes.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProfileImage {
+    pub width: u32,
+    pub height: u32,
+    pub url: String,
+}
+
+/// Multipart form of an image upload.
+#[derive(Debug, ToSchema)]
+pub struct ImageUpload {
+    /// JPEG, PNG, GIF or WebP image. Only the first frame of animations is
+    /// kept.
+    #[schema(value_type = String, format = Binary)]
+    pub image: Vec<u8>,
+}
+
+/// Fields left out are kept as they are.
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+    /// 1 to 50 letters, digits, spaces, and `-` `_` `.` `'`. Surrounding
+    /// whitespace is trimmed, and runs of it collapsed.
+    pub username: Option<String>,
+    /// At most 500 characters, trimmed. Empty clears it.
+    pub bio: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+pub struct ChangeHandleRequest {
+    /// 3 to 30 ASCII letters, digits and `_`, not only digits. A leading `@`
+    /// is dropped. Unique regardless of case, which is kept for display.
+    pub handle: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HandleAvailability {
+    /// The handle as it would be stored.
+    pub handle: String,
+    pub available: bool,
+    /// Why the handle isn't available.
+    pub reason: Option<HandleUnavailableReason>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleUnavailableReason {
+    /// Kept for the service itself, such as `admin`.
+    Reserved,
+    /// Someone has the handle, or had it until recently.
+    Taken,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchQuery {
+    /// At least 2 characters, matched against usernames and handles.
+    pub q: String,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Results per page, 20 by default and at most 50.
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserSearchPage {
+    /// Best matches first.
+    pub users: Vec<UserProfile>,
+    /// Cursor of the next page, unset on the last one.
+    pub next_cursor: Option<String>,
+}
+
+/// A search result with its rank, higher for better matches.
+#[derive(Debug)]
+pub struct SearchHit {
+    pub user: UserProfile,
+    pub rank: f32,
+}
+
+/// Where a page of search results ends, handed to clients as an opaque
+/// string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchCursor {
+    pub rank: f32,
+    pub id: i32,
+}
+
+impl SearchCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.rank, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<SearchCursor> {
+        let cursor = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (rank, id) = cursor.split_once(':')?;
+
+        Some(SearchCursor {
+            rank: rank.parse().ok()?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct PendingEmailChange {
+    pub user_id: i32,
+    pub new_email: String,
+    pub code: String,
+    pub created_at: OffsetDateTime,
+    pub attempts: i32,
+}
+
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+pub struct ChangeEmailRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+pub struct ConfirmEmailChangeRequest {
+    pub code: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_cursors_round_trip() {
+        let cursor = SearchCursor {
+            rank: 2.718_281_7,
+            id: 42,
+        };
+        assert_eq!(SearchCursor::decode(&cursor.encode()), Some(cursor));
+
+        assert_eq!(SearchCursor::decode("not a cursor"), None);
+        assert_eq!(SearchCursor::decode(&URL_SAFE_NO_PAD.encode("1.5")), None);
+    }
 }
@@ -1,8 +1,24 @@
 use std::sync::Arc;
 
 use axum::{async_trait, Extension};
+use sqlx::types::Json;
+use time::{Duration, OffsetDateTime};
 
-use crate::{db::Db, features::users::models::UserProfile};
+use crate::{
+    db::Db,
+    error::AppError,
+    features::{
+        outbox,
+        users::{
+            images::ImageKind,
+            models::{
+                PendingEmailChange, PrivacySettings, ProfileImage, SearchCursor, SearchHit,
+                UserProfile, Visibility,
+            },
+        },
+    },
+    mail::Email,
+};
 
 pub type UserRepoExt = Extension<Arc<UserRepo>>;
 
@@ -12,24 +28,535 @@ pub struct UserRepo {
 
 #[async_trait]
 pub trait UserRepoImpl {
-    async fn find_by_id(&self, id: i32) -> Option<UserProfile>;
-    async fn find_by_email(&self, email: &str) -> Option<UserProfile>;
+    /// Finds a verified user, as seen by the signed-in user `viewer`, or an
+    /// anonymous caller when `None`. Parts hidden from them are left out.
+    async fn find_by_id(
+        &self,
+        id: i32,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError>;
+
+    /// Like `find_by_id`, but finds nobody if the user doesn't let `viewer`
+    /// look them up by email.
+    async fn find_by_email(
+        &self,
+        email: &str,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError>;
+
+    async fn find_by_handle(
+        &self,
+        handle: &str,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError>;
+
+    /// Verified users whose username or handle matches `query`, best matches
+    /// first, starting after `cursor`. `query` is expected in lowercase.
+    async fn search(
+        &self,
+        query: &str,
+        viewer: Option<i32>,
+        cursor: Option<SearchCursor>,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, AppError>;
+
+    /// Returns `None` if the user is unverified.
+    async fn get_privacy_settings(&self, user_id: i32)
+        -> Result<Option<PrivacySettings>, AppError>;
+
+    /// Sets the settings that are `Some`. Returns `None` if the user is
+    /// unverified.
+    async fn update_privacy_settings(
+        &self,
+        user_id: i32,
+        email_lookup: Option<Visibility>,
+        bio: Option<Visibility>,
+    ) -> Result<Option<PrivacySettings>, AppError>;
+
+    /// The current handle of the user who had `handle` until recently.
+    async fn find_handle_redirect(&self, handle: &str) -> Result<Option<String>, AppError>;
+
+    /// Whether someone has `handle`, or had it until recently.
+    async fn is_handle_taken(&self, handle: &str) -> Result<bool, AppError>;
+
+    /// Gives the user `handle`, keeping their previous one for them and
+    /// redirecting to the new one for `redirect_period`. Fails with
+    /// `HandleTaken` if someone else holds it, and with `RetryLater` within
+    /// `cooldown` of the last change, unless only the case changes. Returns
+    /// `None` if the user is unverified.
+    async fn change_handle(
+        &self,
+        user_id: i32,
+        handle: &str,
+        cooldown: Duration,
+        redirect_period: Duration,
+    ) -> Result<Option<UserProfile>, AppError>;
+
+    /// Sets the fields that are `Some`, bumping `updated_at` if anything
+    /// changed. Returns the profile, or `None` if the user is unverified.
+    async fn update_profile(
+        &self,
+        user_id: i32,
+        username: Option<&str>,
+        bio: Option<&str>,
+    ) -> Result<Option<UserProfile>, AppError>;
+
+    /// Replaces the avatar or banner of the user with `images`, stored under
+    /// the blob key prefix `key`, or removes it when `None`. Returns the
+    /// profile along with the key of the replaced images, or `None` if the user
+    /// is unverified.
+    async fn set_profile_image(
+        &self,
+        user_id: i32,
+        kind: ImageKind,
+        image: Option<(&str, &[ProfileImage])>,
+    ) -> Result<Option<(UserProfile, Option<String>)>, AppError>;
+
+    /// Records a pending change of the user's email, replacing any earlier one,
+    /// and queues `mail` in the same transaction. Fails with `RetryLater`
+    /// within `cooldown` of the last request. Returns `false` if the new
+    /// address belongs to a verified account.
+    async fn request_email_change(
+        &self,
+        user_id: i32,
+        new_email: &str,
+        code: &str,
+        cooldown: Duration,
+        mail: &[Email],
+    ) -> Result<bool, AppError>;
+
+    async fn get_pending_email_change(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<PendingEmailChange>, AppError>;
+
+    /// Counts an attempt at the code of the user's pending email change.
+    /// Returns the number of attempts so far, or `None` if the limit has
+    /// already been reached.
+    async fn record_email_change_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError>;
+
+    /// Swaps the user's email for the pending one, discarding any unverified
+    /// registration holding the new address. Returns `false` if the change is no
+    /// longer pending or a verified account has taken the address in the meantime.
+    async fn confirm_email_change(&self, change: &PendingEmailChange) -> Result<bool, AppError>;
 }
 
 #[async_trait]
 impl UserRepoImpl for UserRepo {
-    async fn find_by_id(&self, id: i32) -> Option<UserProfile> {
-        sqlx::query_file_as!(UserProfile, "queries/users/get_profile_by_id.sql", id)
-            .fetch_optional(&self.db)
-            .await
-            .unwrap()
+    async fn find_by_id(
+        &self,
+        id: i32,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError> {
+        let user = sqlx::query_file_as!(
+            UserProfile,
+            "queries/users/get_profile_by_id.sql",
+            id,
+            viewer
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn find_by_email(
+        &self,
+        email: &str,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError> {
+        let user = sqlx::query_file_as!(
+            UserProfile,
+            "queries/users/get_profile_by_email.sql",
+            email,
+            viewer
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn find_by_handle(
+        &self,
+        handle: &str,
+        viewer: Option<i32>,
+    ) -> Result<Option<UserProfile>, AppError> {
+        let user = sqlx::query_file_as!(
+            UserProfile,
+            "queries/users/get_profile_by_handle.sql",
+            handle,
+            viewer
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        viewer: Option<i32>,
+        cursor: Option<SearchCursor>,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        let prefix_pattern = format!(
+            "{}%",
+            query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+
+        let rows = sqlx::query_file!(
+            "queries/users/search_users.sql",
+            query,
+            prefix_pattern,
+            cursor.map(|cursor| cursor.rank),
+            cursor.map(|cursor| cursor.id),
+            limit,
+            viewer
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let hits = rows
+            .into_iter()
+            .map(|row| SearchHit {
+                user: UserProfile {
+                    id: row.id,
+                    username: row.username,
+                    handle: row.handle,
+                    bio: row.bio,
+                    avatar: row.avatar,
+                    banner: row.banner,
+                    updated_at: row.updated_at,
+                },
+                rank: row.rank,
+            })
+            .collect();
+
+        Ok(hits)
+    }
+
+    async fn get_privacy_settings(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<PrivacySettings>, AppError> {
+        let settings = sqlx::query_file_as!(
+            PrivacySettings,
+            "queries/users/get_privacy_settings.sql",
+            user_id
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(settings)
     }
 
-    async fn find_by_email(&self, email: &str) -> Option<UserProfile> {
-        sqlx::query_file_as!(UserProfile, "queries/users/get_profile_by_email.sql", email)
+    async fn update_privacy_settings(
+        &self,
+        user_id: i32,
+        email_lookup: Option<Visibility>,
+        bio: Option<Visibility>,
+    ) -> Result<Option<PrivacySettings>, AppError> {
+        let settings = sqlx::query_file_as!(
+            PrivacySettings,
+            "queries/users/update_privacy_settings.sql",
+            user_id,
+            email_lookup as _,
+            bio as _
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(settings)
+    }
+
+    async fn find_handle_redirect(&self, handle: &str) -> Result<Option<String>, AppError> {
+        let handle = sqlx::query_file_scalar!("queries/users/get_handle_redirect.sql", handle)
             .fetch_optional(&self.db)
-            .await
-            .unwrap()
+            .await?;
+
+        Ok(handle)
+    }
+
+    async fn is_handle_taken(&self, handle: &str) -> Result<bool, AppError> {
+        let taken = sqlx::query_file_scalar!("queries/users/is_handle_taken.sql", handle)
+            .fetch_one(&self.db)
+            .await?;
+
+        Ok(taken)
+    }
+
+    async fn change_handle(
+        &self,
+        user_id: i32,
+        handle: &str,
+        cooldown: Duration,
+        redirect_period: Duration,
+    ) -> Result<Option<UserProfile>, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(current) = sqlx::query_file!("queries/users/lock_handle.sql", user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // Changing only the case keeps the handle, it can't be taken or
+        // redirect anywhere.
+        let is_own = current
+            .handle
+            .as_deref()
+            .is_some_and(|current| current.eq_ignore_ascii_case(handle));
+        let previous_handle = current.handle.filter(|_| !is_own);
+
+        if previous_handle.is_some() {
+            if let Some(changed_at) = current.handle_changed_at {
+                let retry_after = changed_at + cooldown - OffsetDateTime::now_utc();
+
+                if retry_after.is_positive() {
+                    return Err(AppError::RetryLater {
+                        retry_after: retry_after.whole_seconds(),
+                    });
+                }
+            }
+        }
+
+        sqlx::query_file!("queries/users/release_previous_handle.sql", user_id, handle)
+            .execute(&mut *tx)
+            .await?;
+
+        if !is_own
+            && sqlx::query_file_scalar!("queries/users/is_handle_taken.sql", handle)
+                .fetch_one(&mut *tx)
+                .await?
+        {
+            return Err(AppError::HandleTaken);
+        }
+
+        let result = sqlx::query_file_as!(
+            UserProfile,
+            "queries/users/update_handle.sql",
+            user_id,
+            handle
+        )
+        .fetch_one(&mut *tx)
+        .await;
+
+        let user = match result {
+            Ok(user) => user,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Err(AppError::HandleTaken)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(previous_handle) = previous_handle {
+            sqlx::query_file!(
+                "queries/users/create_previous_handle.sql",
+                previous_handle,
+                user_id,
+                OffsetDateTime::now_utc() + redirect_period
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(Some(user))
+    }
+
+    async fn set_profile_image(
+        &self,
+        user_id: i32,
+        kind: ImageKind,
+        image: Option<(&str, &[ProfileImage])>,
+    ) -> Result<Option<(UserProfile, Option<String>)>, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(previous) = sqlx::query_file!("queries/users/lock_profile_images.sql", user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let (key, images) = image.unzip();
+        let images = images.map(Json);
+
+        let (user, previous_key) = match kind {
+            ImageKind::Avatar => {
+                let user = sqlx::query_file_as!(
+                    UserProfile,
+                    "queries/users/update_avatar.sql",
+                    user_id,
+                    key,
+                    images as _
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                (user, previous.avatar_key)
+            }
+            ImageKind::Banner => {
+                let user = sqlx::query_file_as!(
+                    UserProfile,
+                    "queries/users/update_banner.sql",
+                    user_id,
+                    key,
+                    images as _
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                (user, previous.banner_key)
+            }
+        };
+
+        tx.commit().await?;
+
+        Ok(Some((user, previous_key)))
+    }
+
+    async fn update_profile(
+        &self,
+        user_id: i32,
+        username: Option<&str>,
+        bio: Option<&str>,
+    ) -> Result<Option<UserProfile>, AppError> {
+        let user = sqlx::query_file_as!(
+            UserProfile,
+            "queries/users/update_profile.sql",
+            user_id,
+            username,
+            bio
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn request_email_change(
+        &self,
+        user_id: i32,
+        new_email: &str,
+        code: &str,
+        cooldown: Duration,
+        mail: &[Email],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let requested_at =
+            sqlx::query_file_scalar!("queries/users/lock_pending_email_change.sql", user_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        if let Some(requested_at) = requested_at {
+            let retry_after = requested_at + cooldown - OffsetDateTime::now_utc();
+
+            if retry_after.is_positive() {
+                return Err(AppError::RetryLater {
+                    retry_after: retry_after.whole_seconds(),
+                });
+            }
+        }
+
+        let requested = sqlx::query_file_scalar!(
+            "queries/users/create_pending_email_change.sql",
+            user_id,
+            new_email,
+            code
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if requested.is_none() {
+            return Ok(false);
+        }
+
+        outbox::repositories::enqueue(&mut tx, mail).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_pending_email_change(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<PendingEmailChange>, AppError> {
+        let change = sqlx::query_file_as!(
+            PendingEmailChange,
+            "queries/users/get_pending_email_change.sql",
+            user_id
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(change)
+    }
+
+    async fn record_email_change_attempt(
+        &self,
+        user_id: i32,
+        max_attempts: i32,
+    ) -> Result<Option<i32>, AppError> {
+        let attempts = sqlx::query_file_scalar!(
+            "queries/users/record_email_change_attempt.sql",
+            user_id,
+            max_attempts
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    async fn confirm_email_change(&self, change: &PendingEmailChange) -> Result<bool, AppError> {
+        let mut tx = self.db.begin().await?;
+
+        let new_email = sqlx::query_file_scalar!(
+            "queries/users/delete_pending_email_change.sql",
+            change.user_id,
+            change.new_email,
+            change.code
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(new_email) = new_email else {
+            return Ok(false);
+        };
+
+        sqlx::query_file!(
+            "queries/users/delete_unverified_user_by_email.sql",
+            new_email
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query_file!("queries/users/update_email.sql", change.user_id, new_email)
+            .execute(&mut *tx)
+            .await;
+
+        match result {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
     }
 }
 
@@ -48,7 +575,8 @@ mod tests {
             "abc@def.com",
             "def",
             "ghi",
-            "jkl"
+            "jkl",
+            None::<String>
         )
         .fetch_optional(&pool.clone())
         .await
@@ -56,10 +584,13 @@ mod tests {
         .unwrap()
         .user_id;
 
-        let user = repo.find_by_id(user_id).await;
+        let user = repo.find_by_id(user_id, None).await.unwrap();
         assert!(user.is_none());
 
-        let user = repo.find_by_email("abc@def.com").await;
+        let user = repo
+            .find_by_email("abc@def.com", Some(user_id))
+            .await
+            .unwrap();
         assert!(user.is_none());
 
         let auth_user = sqlx::query_file!("queries/auth/verify_email.sql", "abc@def.com")
@@ -67,8 +598,506 @@ mod tests {
             .await
             .unwrap();
 
-        let user = repo.find_by_id(user_id).await.unwrap();
+        let user = repo.find_by_id(user_id, None).await.unwrap().unwrap();
         assert_eq!(user.username, "ghi");
         assert_eq!(auth_user.email, "abc@def.com")
     }
+
+    async fn create_verified_user(pool: &PgPool, email: &str) -> i32 {
+        let user_id = sqlx::query_file_scalar!(
+            "queries/auth/create_user.sql",
+            email,
+            "a",
+            "b",
+            "c",
+            None::<String>
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        sqlx::query_file!("queries/auth/verify_email.sql", email)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+
+        user_id
+    }
+
+    #[sqlx::test]
+    async fn test_update_profile(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+        let user_id = create_verified_user(&pool, "abc@def.com").await;
+        let before = repo.find_by_id(user_id, None).await.unwrap().unwrap();
+
+        let user = repo
+            .update_profile(user_id, None, Some("Hello"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.username, "b");
+        assert_eq!(user.bio.as_deref(), Some("Hello"));
+        assert!(user.updated_at > before.updated_at);
+
+        let user = repo
+            .update_profile(user_id, Some("Ann"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.username, "Ann");
+        assert_eq!(user.bio.as_deref(), Some("Hello"));
+
+        // Nothing changes, nor does `updated_at`.
+        let unchanged = repo
+            .update_profile(user_id, Some("Ann"), Some("Hello"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged.updated_at, user.updated_at);
+
+        let unverified_id = sqlx::query_file_scalar!(
+            "queries/auth/create_user.sql",
+            "new@def.com",
+            "a",
+            "b",
+            "c",
+            None::<String>
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(repo
+            .update_profile(unverified_id, Some("Eve"), None)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_change_handle(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+        let ann_id = create_verified_user(&pool, "ann@def.com").await;
+        let bob_id = create_verified_user(&pool, "bob@def.com").await;
+        let day = Duration::days(1);
+
+        let ann = repo
+            .change_handle(ann_id, "Ann", day, day)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ann.handle.as_deref(), Some("Ann"));
+        assert_eq!(
+            repo.find_by_handle("aNN", None).await.unwrap().unwrap().id,
+            ann_id
+        );
+        assert!(repo.is_handle_taken("ANN").await.unwrap());
+
+        let result = repo.change_handle(bob_id, "ann", day, day).await;
+        assert!(matches!(result, Err(AppError::HandleTaken)));
+
+        // Changing the case isn't a change.
+        let ann = repo
+            .change_handle(ann_id, "ann", day, day)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ann.handle.as_deref(), Some("ann"));
+
+        let result = repo.change_handle(ann_id, "ann_smith", day, day).await;
+        assert!(matches!(result, Err(AppError::RetryLater { .. })));
+
+        repo.change_handle(ann_id, "ann_smith", Duration::ZERO, day)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(repo.find_by_handle("ann", None).await.unwrap().is_none());
+        assert_eq!(
+            repo.find_handle_redirect("ANN").await.unwrap().as_deref(),
+            Some("ann_smith")
+        );
+
+        // The previous handle is kept for its owner during the redirect.
+        assert!(repo.is_handle_taken("ann").await.unwrap());
+        let result = repo.change_handle(bob_id, "ann", day, day).await;
+        assert!(matches!(result, Err(AppError::HandleTaken)));
+
+        repo.change_handle(ann_id, "ann", Duration::ZERO, day)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(repo.find_handle_redirect("ann").await.unwrap().is_none());
+        assert_eq!(
+            repo.find_handle_redirect("ann_smith")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("ann")
+        );
+
+        // Then it is up for grabs.
+        sqlx::query!("UPDATE previous_handle SET expires_at = now()")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(repo
+            .find_handle_redirect("ann_smith")
+            .await
+            .unwrap()
+            .is_none());
+        let bob = repo
+            .change_handle(bob_id, "ann_smith", day, day)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bob.handle.as_deref(), Some("ann_smith"));
+    }
+
+    #[sqlx::test]
+    async fn test_privacy_settings(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+        let user_id = create_verified_user(&pool, "abc@def.com").await;
+        let other_id = create_verified_user(&pool, "other@def.com").await;
+        repo.update_profile(user_id, None, Some("Hello"))
+            .await
+            .unwrap();
+
+        let settings = repo.get_privacy_settings(user_id).await.unwrap().unwrap();
+        assert_eq!(settings.email_lookup, Visibility::SignedIn);
+        assert_eq!(settings.bio, Visibility::Everyone);
+
+        let bio = |user: Option<UserProfile>| user.unwrap().bio;
+
+        assert!(repo
+            .find_by_email("abc@def.com", None)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo
+            .find_by_email("abc@def.com", Some(other_id))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(bio(repo.find_by_id(user_id, None).await.unwrap()).is_some());
+
+        let settings = repo
+            .update_privacy_settings(
+                user_id,
+                Some(Visibility::Nobody),
+                Some(Visibility::SignedIn),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.email_lookup, Visibility::Nobody);
+        assert_eq!(settings.bio, Visibility::SignedIn);
+
+        assert!(repo
+            .find_by_email("abc@def.com", Some(other_id))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo
+            .find_by_email("abc@def.com", Some(user_id))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(bio(repo.find_by_id(user_id, None).await.unwrap()).is_none());
+        assert_eq!(
+            bio(repo.find_by_id(user_id, Some(other_id)).await.unwrap()).as_deref(),
+            Some("Hello")
+        );
+
+        // Settings left out are kept.
+        let settings = repo
+            .update_privacy_settings(user_id, None, Some(Visibility::Nobody))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.email_lookup, Visibility::Nobody);
+
+        assert!(bio(repo.find_by_id(user_id, Some(other_id)).await.unwrap()).is_none());
+        assert!(bio(repo.find_by_id(user_id, Some(user_id)).await.unwrap()).is_some());
+
+        sqlx::query!(
+            "UPDATE gossip_user SET handle = 'abc' WHERE id = $1",
+            user_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(bio(repo.find_by_handle("abc", Some(other_id)).await.unwrap()).is_none());
+        let hits = repo.search("abc", Some(other_id), None, 10).await.unwrap();
+        assert!(hits[0].user.bio.is_none());
+    }
+
+    #[sqlx::test]
+    async fn test_search(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+
+        let mut ids = Vec::new();
+        for (email, username, handle) in [
+            ("ann@def.com", "Ann Smith", "ann"),
+            ("annabel@def.com", "Annabel Lee", "annabel"),
+            ("joanna@def.com", "Joanna", "jo_anna"),
+            ("bob@def.com", "Bob Stone", "bobby"),
+            ("anne@def.com", "Anne Blocked", "anne"),
+        ] {
+            let user_id = create_verified_user(&pool, email).await;
+            sqlx::query!(
+                "UPDATE gossip_user SET username = $2, handle = $3 WHERE id = $1",
+                user_id,
+                username,
+                handle
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            ids.push(user_id);
+        }
+
+        sqlx::query!("UPDATE gossip_user SET blocked_at = now() WHERE handle = 'anne'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query_file!(
+            "queries/auth/create_user.sql",
+            "unverified@def.com",
+            "a",
+            "Annie",
+            "c",
+            None::<String>
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let hits = repo.search("ann", None, None, 10).await.unwrap();
+        let found = hits.iter().map(|hit| hit.user.id).collect::<Vec<_>>();
+        assert_eq!(found[..2], [ids[0], ids[1]]);
+        assert!(!found.contains(&ids[3]));
+        assert!(!found.contains(&ids[4]));
+        assert!(hits.iter().all(|hit| hit.user.username != "Annie"));
+        assert!(hits.windows(2).all(|pair| pair[0].rank >= pair[1].rank));
+
+        // Pages pick up where the previous one ended.
+        let first_page = repo.search("ann", None, None, 1).await.unwrap();
+        let cursor = SearchCursor {
+            rank: first_page[0].rank,
+            id: first_page[0].user.id,
+        };
+        let second_page = repo.search("ann", None, Some(cursor), 10).await.unwrap();
+        let rest = second_page
+            .iter()
+            .map(|hit| hit.user.id)
+            .collect::<Vec<_>>();
+        assert_eq!(rest, found[1..]);
+
+        // Words after the first are matched too.
+        let hits = repo.search("stone", None, None, 10).await.unwrap();
+        assert_eq!(hits[0].user.id, ids[3]);
+
+        let hits = repo.search("jo_anna", None, None, 10).await.unwrap();
+        assert_eq!(hits[0].user.id, ids[2]);
+
+        assert!(repo.search("zzz", None, None, 10).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_set_profile_image(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+        let user_id = create_verified_user(&pool, "abc@def.com").await;
+        let images = |key: &str| {
+            vec![ProfileImage {
+                width: 64,
+                height: 64,
+                url: format!("/blobs/{key}/64.jpg"),
+            }]
+        };
+
+        let (user, previous_key) = repo
+            .set_profile_image(user_id, ImageKind::Avatar, Some(("a", &images("a"))))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.avatar.unwrap().0[0].url, "/blobs/a/64.jpg");
+        assert!(user.banner.is_none());
+        assert!(previous_key.is_none());
+
+        let (_, previous_key) = repo
+            .set_profile_image(user_id, ImageKind::Banner, Some(("b", &images("b"))))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(previous_key.is_none());
+
+        let (user, previous_key) = repo
+            .set_profile_image(user_id, ImageKind::Avatar, Some(("c", &images("c"))))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(previous_key.as_deref(), Some("a"));
+        assert_eq!(user.avatar.unwrap().0[0].url, "/blobs/c/64.jpg");
+
+        let (user, previous_key) = repo
+            .set_profile_image(user_id, ImageKind::Avatar, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(previous_key.as_deref(), Some("c"));
+        assert!(user.avatar.is_none());
+
+        let user = repo.find_by_id(user_id, None).await.unwrap().unwrap();
+        assert!(user.avatar.is_none());
+        assert_eq!(user.banner.unwrap().0[0].url, "/blobs/b/64.jpg");
+    }
+
+    #[sqlx::test]
+    async fn test_email_change(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+
+        let user_id = create_verified_user(&pool, "old@def.com").await;
+        create_verified_user(&pool, "taken@def.com").await;
+
+        // A squatting unverified registration doesn't block the change.
+        sqlx::query_file!(
+            "queries/auth/create_user.sql",
+            "new@def.com",
+            "a",
+            "b",
+            "c",
+            None::<String>
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert!(!repo
+            .request_email_change(user_id, "taken@def.com", "123456", Duration::ZERO, &[])
+            .await
+            .unwrap());
+        assert!(repo
+            .request_email_change(user_id, "new@def.com", "123456", Duration::ZERO, &[])
+            .await
+            .unwrap());
+
+        let pending = repo
+            .get_pending_email_change(user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.new_email, "new@def.com");
+        assert_eq!(pending.code, "123456");
+
+        // Another code can't be sent right away.
+        assert!(matches!(
+            repo.request_email_change(user_id, "new@def.com", "654321", Duration::minutes(1), &[])
+                .await,
+            Err(AppError::RetryLater { .. })
+        ));
+
+        // The email only changes once confirmed.
+        assert!(repo
+            .find_by_email("old@def.com", Some(user_id))
+            .await
+            .unwrap()
+            .is_some());
+
+        assert!(repo.confirm_email_change(&pending).await.unwrap());
+        assert_eq!(
+            repo.find_by_email("new@def.com", Some(user_id))
+                .await
+                .unwrap()
+                .unwrap()
+                .id,
+            user_id
+        );
+        assert!(repo
+            .find_by_email("old@def.com", Some(user_id))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo
+            .get_pending_email_change(user_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!repo.confirm_email_change(&pending).await.unwrap());
+    }
+
+    #[sqlx::test]
+    async fn test_email_change_fails_if_address_was_taken(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+
+        let user_id = create_verified_user(&pool, "old@def.com").await;
+
+        assert!(repo
+            .request_email_change(user_id, "new@def.com", "123456", Duration::ZERO, &[])
+            .await
+            .unwrap());
+        let pending = repo
+            .get_pending_email_change(user_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        create_verified_user(&pool, "new@def.com").await;
+
+        assert!(!repo.confirm_email_change(&pending).await.unwrap());
+        assert_eq!(
+            repo.find_by_email("old@def.com", Some(user_id))
+                .await
+                .unwrap()
+                .unwrap()
+                .id,
+            user_id
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_email_change_attempts_are_limited(pool: PgPool) {
+        let repo = UserRepo { db: pool.clone() };
+
+        let user_id = create_verified_user(&pool, "old@def.com").await;
+        assert_eq!(
+            repo.record_email_change_attempt(user_id, 2).await.unwrap(),
+            None
+        );
+
+        repo.request_email_change(user_id, "new@def.com", "123456", Duration::ZERO, &[])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repo.record_email_change_attempt(user_id, 2).await.unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            repo.record_email_change_attempt(user_id, 2).await.unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            repo.record_email_change_attempt(user_id, 2).await.unwrap(),
+            None
+        );
+
+        let pending = repo
+            .get_pending_email_change(user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.attempts, 2);
+
+        // Requesting a new code starts over.
+        repo.request_email_change(user_id, "new@def.com", "654321", Duration::ZERO, &[])
+            .await
+            .unwrap();
+
+        let pending = repo
+            .get_pending_email_change(user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending.code, "654321");
+        assert_eq!(pending.attempts, 0);
+    }
 }
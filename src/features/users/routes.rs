@@ -1,19 +1,61 @@
 use std::sync::Arc;
 
-use axum::{extract::Path, http::StatusCode, routing::get, Extension, Json, Router};
+use axum::{
+    extract::{multipart::MultipartError, DefaultBodyLimit, Multipart, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post, put},
+    Extension, Router,
+};
+use rand::Rng;
+use subtle::ConstantTimeEq;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
 
-use crate::state::AppState;
+use crate::{
+    error::AppError,
+    extract::{Json, Path, Query},
+    features::auth::models::{AuthUser, ClientInfo, OptionalAuth, Principal, Scope},
+    mail::Message,
+    rate_limit::Route,
+    state::AppState,
+};
 
 use super::{
-    models::UserProfile,
+    images::{self, ImageKind},
+    models::{
+        ChangeEmailRequest, ChangeHandleRequest, ConfirmEmailChangeRequest, HandleAvailability,
+        HandleUnavailableReason, ImageUpload, PrivacySettings, ProfileImage, SearchCursor,
+        SearchQuery, UpdatePrivacyRequest, UpdateProfileRequest, UserProfile, UserSearchPage,
+    },
     repositories::{UserRepo, UserRepoExt, UserRepoImpl},
+    validation,
 };
 
 pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
         .route("/:id", get(user_by_id))
         .route("/by-email/:email", get(user_by_email))
-        .route("/me", get(me))
+        .route("/search", get(search))
+        .route("/by-handle/:handle", get(user_by_handle))
+        .route("/handle-availability/:handle", get(handle_availability))
+        .route("/me", get(me).patch(update_me))
+        .route("/me/privacy", get(privacy).patch(update_privacy))
+        .route("/me/handle", put(change_handle))
+        .route(
+            "/me/avatar",
+            put(upload_avatar)
+                .delete(delete_avatar)
+                .layer(DefaultBodyLimit::max(state.config.max_image_upload)),
+        )
+        .route(
+            "/me/banner",
+            put(upload_banner)
+                .delete(delete_banner)
+                .layer(DefaultBodyLimit::max(state.config.max_image_upload)),
+        )
+        .route("/me/email", post(change_email))
+        .route("/me/email/confirm", post(confirm_email_change))
         .layer(Extension(Arc::new(UserRepo {
             db: state.db.clone(),
         })))
@@ -23,42 +65,190 @@ pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
     get,
     path = "/user/{id}",
     responses(
-        (status = 200, body = UserProfile),
-        (status = 404, description = "User not found."),
+        (status = 200, body = UserProfile, description = "The profile, without the parts the user hides from the caller."),
+        (status = 401, body = ProblemDetails, description = "Invalid token."),
+        (status = 404, body = ProblemDetails, description = "User not found."),
     ),
     tag = "users",
+    security(
+        (),
+        ("api_key" = [])
+    )
 )]
 async fn user_by_id(
     Path(id): Path<i32>,
     Extension(repo): UserRepoExt,
-) -> Result<Json<UserProfile>, StatusCode> {
-    let user = repo.find_by_id(id).await;
+    auth: OptionalAuth,
+) -> Result<Json<UserProfile>, AppError> {
+    let user = repo
+        .find_by_id(id, auth.user_id())
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    match user {
-        Some(user) => Ok(Json(user)),
-        None => Err(StatusCode::NOT_FOUND),
-    }
+    Ok(Json(user))
 }
 
 #[utoipa::path(
     get,
     path = "/user/by-email/{email}",
     responses(
-        (status = 200, body = UserProfile),
-        (status = 404, description = "User not found."),
+        (status = 200, body = UserProfile, description = "The profile, without the parts the user hides from the caller."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 404, body = ProblemDetails, description = "User not found, or they can't be found by email by the caller."),
     ),
     tag = "users",
+    security(
+        ("api_key" = [])
+    )
 )]
 async fn user_by_email(
     Path(email): Path<String>,
     Extension(repo): UserRepoExt,
-) -> Result<Json<UserProfile>, StatusCode> {
-    let user = repo.find_by_email(&email).await;
+    principal: Principal,
+) -> Result<Json<UserProfile>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    // Users hiding from the caller are not found either, so lookups can't
+    // tell whether an address has an account.
+    let user = repo
+        .find_by_email(&email, Some(principal.user_id))
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(user))
+}
+
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+const MAX_SEARCH_LIMIT: u32 = 50;
+
+#[utoipa::path(
+    get,
+    path = "/user/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, body = UserSearchPage),
+        (status = 400, body = ProblemDetails, description = "Invalid query or cursor."),
+        (status = 401, body = ProblemDetails, description = "Invalid token."),
+    ),
+    tag = "users",
+    security(
+        (),
+        ("api_key" = [])
+    )
+)]
+async fn search(
+    Query(query): Query<SearchQuery>,
+    Extension(repo): UserRepoExt,
+    auth: OptionalAuth,
+) -> Result<Json<UserSearchPage>, AppError> {
+    let q = validation::search_query(&query.q)?;
+
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|cursor| {
+            SearchCursor::decode(cursor)
+                .ok_or_else(|| AppError::InvalidRequest("Invalid cursor.".to_owned()))
+        })
+        .transpose()?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT) as usize;
+
+    // One more than asked for tells whether there is a next page.
+    let mut hits = repo
+        .search(&q, auth.user_id(), cursor, limit as i64 + 1)
+        .await?;
+
+    let next_cursor = if hits.len() > limit {
+        hits.truncate(limit);
+        hits.last().map(|hit| {
+            SearchCursor {
+                rank: hit.rank,
+                id: hit.user.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(Json(UserSearchPage {
+        users: hits.into_iter().map(|hit| hit.user).collect(),
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/by-handle/{handle}",
+    params(
+        ("handle" = String, Path, description = "Handle, with or without the `@`, in any case."),
+    ),
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 307, description = "A recent previous handle, redirecting to the current one.",
+            headers(("Location" = String))),
+        (status = 401, body = ProblemDetails, description = "Invalid token."),
+        (status = 404, body = ProblemDetails, description = "User not found."),
+    ),
+    tag = "users",
+    security(
+        (),
+        ("api_key" = [])
+    )
+)]
+async fn user_by_handle(
+    Path(handle): Path<String>,
+    Extension(repo): UserRepoExt,
+    auth: OptionalAuth,
+) -> Result<Response, AppError> {
+    let handle = handle.strip_prefix('@').unwrap_or(&handle);
 
-    match user {
-        Some(user) => Ok(Json(user)),
-        None => Err(StatusCode::NOT_FOUND),
+    if let Some(user) = repo.find_by_handle(handle, auth.user_id()).await? {
+        return Ok(Json(user).into_response());
     }
+
+    let current_handle = repo
+        .find_handle_redirect(handle)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Redirect::temporary(&format!("/user/by-handle/{current_handle}")).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/handle-availability/{handle}",
+    responses(
+        (status = 200, body = HandleAvailability),
+        (status = 400, body = ProblemDetails, description = "Invalid handle."),
+    ),
+    tag = "users",
+)]
+async fn handle_availability(
+    Path(handle): Path<String>,
+    Extension(repo): UserRepoExt,
+) -> Result<Json<HandleAvailability>, AppError> {
+    let handle = validation::handle(&handle)?;
+
+    let reason = if validation::is_reserved_handle(&handle) {
+        Some(HandleUnavailableReason::Reserved)
+    } else if repo.is_handle_taken(&handle).await? {
+        Some(HandleUnavailableReason::Taken)
+    } else {
+        None
+    };
+
+    Ok(Json(HandleAvailability {
+        handle,
+        available: reason.is_none(),
+        reason,
+    }))
 }
 
 #[utoipa::path(
@@ -66,7 +256,8 @@ async fn user_by_email(
     path = "/user/me",
     responses(
         (status = 200, body = UserProfile),
-        (status = 401, description = "Unauthorized."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:read` scope."),
     ),
     tag = "users",
     security(
@@ -76,3 +267,495 @@ async fn user_by_email(
 async fn me(user: UserProfile) -> Json<UserProfile> {
     Json(user)
 }
+
+#[utoipa::path(
+    patch,
+    path = "/user/me",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 400, body = ProblemDetails, description = "Invalid username or bio."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    request_body = UpdateProfileRequest,
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn update_me(
+    Extension(repo): UserRepoExt,
+    principal: Principal,
+    Json(body): Json<UpdateProfileRequest>,
+) -> Result<Json<UserProfile>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileWrite)?;
+
+    let username = body
+        .username
+        .as_deref()
+        .map(validation::username)
+        .transpose()?;
+    let bio = body.bio.as_deref().map(validation::bio).transpose()?;
+
+    let user = repo
+        .update_profile(principal.user_id, username.as_deref(), bio.as_deref())
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    get,
+    path = "/user/me/privacy",
+    responses(
+        (status = 200, body = PrivacySettings),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:read` scope."),
+    ),
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn privacy(
+    Extension(repo): UserRepoExt,
+    principal: Principal,
+) -> Result<Json<PrivacySettings>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileRead)?;
+
+    let settings = repo
+        .get_privacy_settings(principal.user_id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(settings))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/user/me/privacy",
+    responses(
+        (status = 200, body = PrivacySettings),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    request_body = UpdatePrivacyRequest,
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn update_privacy(
+    Extension(repo): UserRepoExt,
+    principal: Principal,
+    Json(body): Json<UpdatePrivacyRequest>,
+) -> Result<Json<PrivacySettings>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileWrite)?;
+
+    let settings = repo
+        .update_privacy_settings(principal.user_id, body.email_lookup, body.bio)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(settings))
+}
+
+#[utoipa::path(
+    put,
+    path = "/user/me/handle",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 400, body = ProblemDetails, description = "Invalid or reserved handle."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+        (status = 409, body = ProblemDetails, description = "Handle already taken."),
+        (status = 429, body = ProblemDetails, description = "Handle changed too recently, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = ChangeHandleRequest,
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn change_handle(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(body): Json<ChangeHandleRequest>,
+) -> Result<Json<UserProfile>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileWrite)?;
+
+    let handle = validation::handle(&body.handle)?;
+
+    if validation::is_reserved_handle(&handle) {
+        return Err(AppError::InvalidRequest(
+            "This handle is reserved.".to_owned(),
+        ));
+    }
+
+    let user = repo
+        .change_handle(
+            principal.user_id,
+            &handle,
+            Duration::seconds(state.config.handle_change_cooldown as i64),
+            Duration::seconds(state.config.handle_redirect_period as i64),
+        )
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(user))
+}
+
+#[utoipa::path(
+    put,
+    path = "/user/me/avatar",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 400, body = ProblemDetails, description = "Missing, invalid, too small or too large image."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    request_body(content = ImageUpload, content_type = "multipart/form-data",
+        description = "At least 64x64 pixels, cropped to a square."),
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn upload_avatar(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    multipart: Multipart,
+) -> Result<Json<UserProfile>, AppError> {
+    upload_image(&repo, &state, &principal, ImageKind::Avatar, multipart).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/user/me/avatar",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn delete_avatar(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<UserProfile>, AppError> {
+    delete_image(&repo, &state, &principal, ImageKind::Avatar).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/user/me/banner",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 400, body = ProblemDetails, description = "Missing, invalid, too small or too large image."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    request_body(content = ImageUpload, content_type = "multipart/form-data",
+        description = "At least 600x200 pixels, cropped to a 3:1 aspect ratio."),
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn upload_banner(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    multipart: Multipart,
+) -> Result<Json<UserProfile>, AppError> {
+    upload_image(&repo, &state, &principal, ImageKind::Banner, multipart).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/user/me/banner",
+    responses(
+        (status = 200, body = UserProfile),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "The personal access token lacks the `profile:write` scope."),
+    ),
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn delete_banner(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Json<UserProfile>, AppError> {
+    delete_image(&repo, &state, &principal, ImageKind::Banner).await
+}
+
+async fn upload_image(
+    repo: &UserRepo,
+    state: &AppState,
+    principal: &Principal,
+    kind: ImageKind,
+    multipart: Multipart,
+) -> Result<Json<UserProfile>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileWrite)?;
+
+    let upload = read_image_upload(multipart, state.config.max_image_upload).await?;
+    let encoded_images = tokio::task::spawn_blocking(move || images::process(kind, &upload.image))
+        .await
+        .map_err(anyhow::Error::from)??;
+
+    // A new key for every upload, so caches never serve a replaced image.
+    let key = format!("{}/{}/{}", kind.as_str(), principal.user_id, Uuid::new_v4());
+    let mut profile_images = Vec::new();
+
+    for image in encoded_images {
+        let image_key = format!("{key}/{}.jpg", image.width);
+        state
+            .blob_store
+            .put(&image_key, "image/jpeg", image.data)
+            .await?;
+
+        profile_images.push(ProfileImage {
+            width: image.width,
+            height: image.height,
+            url: state.blob_store.url(&image_key),
+        });
+    }
+
+    let result = repo
+        .set_profile_image(principal.user_id, kind, Some((&key, &profile_images)))
+        .await;
+
+    let Ok(Some((user, previous_key))) = result else {
+        delete_images(state, Some(key)).await;
+        return Err(result.err().unwrap_or(AppError::Unauthorized));
+    };
+
+    delete_images(state, previous_key).await;
+
+    Ok(Json(user))
+}
+
+async fn delete_image(
+    repo: &UserRepo,
+    state: &AppState,
+    principal: &Principal,
+    kind: ImageKind,
+) -> Result<Json<UserProfile>, AppError> {
+    if !principal.is_verified {
+        return Err(AppError::Unauthorized);
+    }
+
+    principal.require_scope(Scope::ProfileWrite)?;
+
+    let (user, previous_key) = repo
+        .set_profile_image(principal.user_id, kind, None)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    delete_images(state, previous_key).await;
+
+    Ok(Json(user))
+}
+
+/// Reads the `image` field of a multipart form, skipping any other.
+async fn read_image_upload(
+    mut multipart: Multipart,
+    max_size: usize,
+) -> Result<ImageUpload, AppError> {
+    let invalid = |e: MultipartError| {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            AppError::InvalidRequest(format!("The image must be at most {max_size} bytes."))
+        } else {
+            AppError::InvalidRequest(e.body_text())
+        }
+    };
+
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        if field.name() == Some("image") {
+            let image = field.bytes().await.map_err(invalid)?;
+
+            return Ok(ImageUpload {
+                image: image.to_vec(),
+            });
+        }
+    }
+
+    Err(AppError::InvalidRequest(
+        "The form has no `image` field.".to_owned(),
+    ))
+}
+
+/// Deletes the images under `key`, which only wastes space if it fails.
+async fn delete_images(state: &AppState, key: Option<String>) {
+    if let Some(key) = key {
+        if let Err(e) = state.blob_store.delete_prefix(&key).await {
+            tracing::warn!("Failed to delete images {}: {:#}", key, e);
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/me/email",
+    responses(
+        (status = 202, description = "Verification code sent to the new address."),
+        (status = 400, body = ProblemDetails, description = "Invalid email address."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 409, body = ProblemDetails, description = "Email already taken."),
+        (status = 429, body = ProblemDetails, description = "A code was sent recently, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = ChangeEmailRequest,
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn change_email(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(body): Json<ChangeEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    let new_email = validation::email(&body.email)?;
+
+    let mut rng = rand::rngs::OsRng;
+    let verification_code = rng.gen_range(100000..999999).to_string();
+
+    let verification_email = state.templates.render(
+        &Message::EmailChangeVerification {
+            code: &verification_code,
+        },
+        user.locale.as_deref(),
+        &user.username,
+        &new_email,
+    );
+
+    let notice_email = state.templates.render(
+        &Message::EmailChangeNotice {
+            new_email: &new_email,
+        },
+        user.locale.as_deref(),
+        &user.username,
+        &user.email,
+    );
+
+    let requested = repo
+        .request_email_change(
+            user.id,
+            &new_email,
+            &verification_code,
+            Duration::seconds(state.config.verification_resend_cooldown as i64),
+            &[verification_email, notice_email],
+        )
+        .await?;
+
+    if !requested {
+        return Err(AppError::EmailTaken);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/me/email/confirm",
+    responses(
+        (status = 204, description = "Email changed."),
+        (status = 400, body = ProblemDetails, description = "Invalid verification code."),
+        (status = 401, body = ProblemDetails, description = "Unauthorized."),
+        (status = 403, body = ProblemDetails, description = "Not allowed with a personal access token."),
+        (status = 409, body = ProblemDetails, description = "Email already taken."),
+        (status = 410, body = ProblemDetails, description = "Verification code expired, request a new one."),
+        (status = 429, body = ProblemDetails, description = "Too many attempts, request a new code. Or too many requests, retry after the number of seconds in `Retry-After`."),
+    ),
+    request_body = ConfirmEmailChangeRequest,
+    tag = "users",
+    security(
+        ("api_key" = [])
+    )
+)]
+async fn confirm_email_change(
+    Extension(repo): UserRepoExt,
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    client: ClientInfo,
+    Json(body): Json<ConfirmEmailChangeRequest>,
+) -> Result<StatusCode, AppError> {
+    let config = &state.config;
+
+    state
+        .rate_limiter
+        .check(
+            Route::Verify,
+            client.ip_address.as_deref(),
+            Some(&user.email),
+        )
+        .await?;
+
+    let pending_change = repo
+        .get_pending_email_change(user.id)
+        .await?
+        .ok_or(AppError::InvalidCode)?;
+
+    if pending_change.attempts >= config.verification_max_attempts {
+        return Err(AppError::TooManyAttempts);
+    }
+
+    let expires_at =
+        pending_change.created_at + Duration::seconds(config.verification_code_ttl as i64);
+
+    if expires_at <= OffsetDateTime::now_utc() {
+        return Err(AppError::CodeExpired);
+    }
+
+    repo.record_email_change_attempt(user.id, config.verification_max_attempts)
+        .await?
+        .ok_or(AppError::TooManyAttempts)?;
+
+    let code_matches: bool = pending_change
+        .code
+        .as_bytes()
+        .ct_eq(body.code.as_bytes())
+        .into();
+
+    if !code_matches {
+        return Err(AppError::InvalidCode);
+    }
+
+    if repo.confirm_email_change(&pending_change).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::EmailTaken)
+    }
+}
@@ -0,0 +1,287 @@
+//! Normalization and validation of what users write into their profile.
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::AppError;
+
+const MAX_USERNAME_LEN: usize = 50;
+const MAX_BIO_LEN: usize = 500;
+const MIN_SEARCH_LEN: usize = 2;
+const MAX_SEARCH_LEN: usize = 100;
+const MIN_HANDLE_LEN: usize = 3;
+const MAX_HANDLE_LEN: usize = 30;
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_EMAIL_LOCAL_PART_LEN: usize = 64;
+
+/// Handles kept for the service and its staff, or that could be mistaken for
+/// them. Compared in lowercase.
+const RESERVED_HANDLES: &[&str] = &[
+    "about",
+    "admin",
+    "administrator",
+    "api",
+    "auth",
+    "gossip",
+    "help",
+    "me",
+    "mod",
+    "moderator",
+    "null",
+    "official",
+    "ops",
+    "root",
+    "security",
+    "settings",
+    "staff",
+    "support",
+    "system",
+    "user",
+    "users",
+];
+
+/// Punctuation allowed in usernames, besides letters, digits and spaces.
+const USERNAME_PUNCTUATION: &[char] = &['-', '_', '.', '\''];
+
+/// A display name in NFC, trimmed, with whitespace runs collapsed to single
+/// spaces. Only letters, digits, spaces and `USERNAME_PUNCTUATION` are
+/// allowed.
+pub fn username(raw: &str) -> Result<String, AppError> {
+    let username = raw
+        .nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let length = username.chars().count();
+
+    if length == 0 || length > MAX_USERNAME_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "Usernames must be 1 to {MAX_USERNAME_LEN} characters long."
+        )));
+    }
+
+    let allowed = |c: char| c.is_alphanumeric() || c == ' ' || USERNAME_PUNCTUATION.contains(&c);
+
+    if !username.chars().all(allowed) {
+        return Err(AppError::InvalidRequest(
+            "Usernames may only contain letters, digits, spaces, and - _ . '".to_owned(),
+        ));
+    }
+
+    Ok(username)
+}
+
+/// A bio in NFC, trimmed, with `\n` line breaks. It may be empty.
+pub fn bio(raw: &str) -> Result<String, AppError> {
+    let bio = raw.nfc().collect::<String>().replace("\r\n", "\n");
+    let bio = bio.trim();
+
+    if bio.chars().count() > MAX_BIO_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "Bios must be at most {MAX_BIO_LEN} characters long."
+        )));
+    }
+
+    if bio.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(AppError::InvalidRequest(
+            "Bios may not contain control characters.".to_owned(),
+        ));
+    }
+
+    Ok(bio.to_owned())
+}
+
+/// A handle without its leading `@`. Only ASCII letters, digits and `_` are
+/// allowed, and not digits alone, which would read like a user id.
+pub fn handle(raw: &str) -> Result<String, AppError> {
+    let raw = raw.trim();
+    let handle = raw.strip_prefix('@').unwrap_or(raw);
+
+    if handle.len() < MIN_HANDLE_LEN || handle.len() > MAX_HANDLE_LEN {
+        return Err(AppError::InvalidRequest(format!(
+            "Handles must be {MIN_HANDLE_LEN} to {MAX_HANDLE_LEN} characters long."
+        )));
+    }
+
+    if !handle
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(AppError::InvalidRequest(
+            "Handles may only contain ASCII letters, digits, and _".to_owned(),
+        ));
+    }
+
+    if handle.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::InvalidRequest(
+            "Handles may not be only digits.".to_owned(),
+        ));
+    }
+
+    Ok(handle.to_owned())
+}
+
+pub fn is_reserved_handle(handle: &str) -> bool {
+    RESERVED_HANDLES.contains(&handle.to_ascii_lowercase().as_str())
+}
+
+/// A search query in NFC and lowercase, trimmed, with whitespace runs
+/// collapsed and without a leading `@`, so handles are found either way.
+pub fn search_query(raw: &str) -> Result<String, AppError> {
+    let query = raw
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let query = query.strip_prefix('@').unwrap_or(&query);
+
+    let length = query.chars().count();
+
+    if !(MIN_SEARCH_LEN..=MAX_SEARCH_LEN).contains(&length) {
+        return Err(AppError::InvalidRequest(format!(
+            "Searches must be {MIN_SEARCH_LEN} to {MAX_SEARCH_LEN} characters long."
+        )));
+    }
+
+    Ok(query.to_owned())
+}
+
+/// An email address, trimmed, with its domain in lowercase. Only the common
+/// `local@domain.tld` shape is accepted: no quoted local parts, IP literals or
+/// dotless domains.
+pub fn email(raw: &str) -> Result<String, AppError> {
+    let invalid = || AppError::InvalidRequest("Invalid email address.".to_owned());
+
+    let email = raw.trim();
+    let (local_part, domain) = email.rsplit_once('@').ok_or_else(invalid)?;
+    let domain = domain.to_ascii_lowercase();
+
+    if email.len() > MAX_EMAIL_LEN
+        || local_part.is_empty()
+        || local_part.len() > MAX_EMAIL_LOCAL_PART_LEN
+        || local_part
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '@' || c == '"')
+    {
+        return Err(invalid());
+    }
+
+    let labels = domain.split('.').collect::<Vec<_>>();
+
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+    };
+
+    if labels.len() < 2 || !labels.iter().all(valid_label) {
+        return Err(invalid());
+    }
+
+    Ok(format!("{local_part}@{domain}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_usernames() {
+        assert_eq!(username("  Ann   Smith ").unwrap(), "Ann Smith");
+        assert_eq!(username("O'Brien-Smith_2.0").unwrap(), "O'Brien-Smith_2.0");
+        assert_eq!(username("Zoë\tK").unwrap(), "Zoë K");
+
+        // A decomposed "é" is stored composed.
+        assert_eq!(username("Rene\u{301}").unwrap(), "Ren\u{e9}");
+        assert_eq!(username("Rene\u{301}").unwrap().chars().count(), 4);
+
+        assert_eq!(username("李小龍").unwrap(), "李小龍");
+    }
+
+    #[test]
+    fn rejects_invalid_usernames() {
+        assert!(username("").is_err());
+        assert!(username("   ").is_err());
+        assert!(username(&"a".repeat(MAX_USERNAME_LEN + 1)).is_err());
+        assert!(username(&"é".repeat(MAX_USERNAME_LEN)).is_ok());
+        assert!(username("<script>").is_err());
+        assert!(username("ann@c.com").is_err());
+        assert!(username("Ann\u{200b}").is_err());
+        assert!(username("Ann 😀").is_err());
+    }
+
+    #[test]
+    fn normalizes_bios() {
+        assert_eq!(bio("").unwrap(), "");
+        assert_eq!(bio("  Hi!\r\nI gossip. \n").unwrap(), "Hi!\nI gossip.");
+        assert_eq!(bio("Cafe\u{301} 😀").unwrap(), "Caf\u{e9} 😀");
+    }
+
+    #[test]
+    fn rejects_invalid_bios() {
+        assert!(bio(&"a".repeat(MAX_BIO_LEN + 1)).is_err());
+        assert!(bio(&"a".repeat(MAX_BIO_LEN)).is_ok());
+        assert!(bio("bell\u{7}").is_err());
+        assert!(bio("tab\there").is_err());
+    }
+
+    #[test]
+    fn validates_emails() {
+        assert_eq!(
+            email(" Ann.Smith+x@Example.COM ").unwrap(),
+            "Ann.Smith+x@example.com"
+        );
+        assert_eq!(email("a@b.c").unwrap(), "a@b.c");
+        assert_eq!(email("zoë@bücher.de").unwrap(), "zoë@bücher.de");
+
+        assert!(email("").is_err());
+        assert!(email("ann").is_err());
+        assert!(email("@example.com").is_err());
+        assert!(email("ann@").is_err());
+        assert!(email("ann@localhost").is_err());
+        assert!(email("ann@@example.com").is_err());
+        assert!(email("ann smith@example.com").is_err());
+        assert!(email("ann@example..com").is_err());
+        assert!(email("ann@-example.com").is_err());
+        assert!(email("ann@example.com\r\nBcc: x@y.z").is_err());
+        assert!(email(&format!("{}@example.com", "a".repeat(65))).is_err());
+    }
+
+    #[test]
+    fn validates_handles() {
+        assert_eq!(handle("Ann_Smith").unwrap(), "Ann_Smith");
+        assert_eq!(handle(" @ann ").unwrap(), "ann");
+        assert_eq!(handle("ann2000").unwrap(), "ann2000");
+
+        assert!(handle("an").is_err());
+        assert!(handle("@an").is_err());
+        assert!(handle(&"a".repeat(MAX_HANDLE_LEN + 1)).is_err());
+        assert!(handle("ann smith").is_err());
+        assert!(handle("ann.smith").is_err());
+        assert!(handle("zoë").is_err());
+        assert!(handle("@@ann").is_err());
+        assert!(handle("12345").is_err());
+    }
+
+    #[test]
+    fn normalizes_search_queries() {
+        assert_eq!(search_query("  Ann   SMITH ").unwrap(), "ann smith");
+        assert_eq!(search_query("@Ann_S").unwrap(), "ann_s");
+        assert_eq!(search_query("Rene\u{301}").unwrap(), "ren\u{e9}");
+
+        assert!(search_query("a").is_err());
+        assert!(search_query("@a").is_err());
+        assert!(search_query(&"a".repeat(MAX_SEARCH_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn reserves_handles_regardless_of_case() {
+        assert!(is_reserved_handle("admin"));
+        assert!(is_reserved_handle("Admin"));
+        assert!(!is_reserved_handle("admins"));
+    }
+}
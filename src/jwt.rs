@@ -1,32 +1,389 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use jsonwebtoken::{DecodingKey, TokenData, Validation};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use super::features::auth::models::TokenClaims;
+use anyhow::{bail, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    errors::ErrorKind,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::Serialize;
+
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo, followed by the 32 bytes of
+/// the key.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// The Ed25519 keys access tokens are signed and verified with, each one
+/// identified by the `kid` header of the tokens it signed.
+///
+/// Only the active key signs. To rotate, add the new key and make it active
+/// once verifiers had time to refresh their copy of the JWKS. The old key keeps
+/// verifying the tokens it signed, and can be removed once those have expired.
+pub struct JwtKeys {
+    active_kid: String,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, DecodingKey>,
+    jwks: JwkSet,
+}
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtKeys")
+            .field("active_kid", &self.active_kid)
+            .field("kids", &self.decoding_keys.keys())
+            .finish()
+    }
+}
+
+impl JwtKeys {
+    /// Loads every `<kid>.pem` file in `dir`, each holding either a PKCS#8
+    /// private key or, for retired keys, just the public key. The active key
+    /// must be a private key.
+    ///
+    /// A key can be generated with `openssl genpkey -algorithm ed25519`.
+    pub fn load(dir: &Path, active_kid: &str) -> anyhow::Result<JwtKeys> {
+        let mut encoding_key = None;
+        let mut decoding_keys = HashMap::new();
+        let mut jwks = Vec::new();
+
+        for file in fs::read_dir(dir).with_context(|| format!("reading {dir:?}"))? {
+            let path = file?.path();
+
+            if path.extension().is_none_or(|extension| extension != "pem") {
+                continue;
+            }
+
+            let kid = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("invalid key file name {path:?}"))?
+                .to_owned();
+
+            let pem = fs::read(&path).with_context(|| format!("reading {path:?}"))?;
+            let pem = pem::parse(pem).with_context(|| format!("parsing {path:?}"))?;
+
+            let public_key = match pem.tag() {
+                "PRIVATE KEY" => {
+                    let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+                        .map_err(|e| anyhow::anyhow!("{path:?} is not an Ed25519 key: {e}"))?;
+
+                    if kid == active_kid {
+                        encoding_key = Some(EncodingKey::from_ed_der(pem.contents()));
+                    }
+
+                    key_pair.public_key().as_ref().to_vec()
+                }
+                "PUBLIC KEY" => match pem.contents().strip_prefix(&ED25519_SPKI_PREFIX) {
+                    Some(public_key) if public_key.len() == 32 => public_key.to_vec(),
+                    _ => bail!("{path:?} is not an Ed25519 public key"),
+                },
+                tag => bail!("{path:?} holds a {tag}, not a key"),
+            };
+
+            let jwk = Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(KeyAlgorithm::EdDSA),
+                    key_id: Some(kid.clone()),
+                    ..Default::default()
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(public_key),
+                }),
+            };
+
+            decoding_keys.insert(kid, DecodingKey::from_jwk(&jwk)?);
+            jwks.push(jwk);
+        }
+
+        let Some(encoding_key) = encoding_key else {
+            bail!("No private key found for the active key {active_kid} in {dir:?}");
+        };
+
+        jwks.sort_by(|a, b| a.common.key_id.cmp(&b.common.key_id));
+
+        Ok(JwtKeys {
+            active_kid: active_kid.to_owned(),
+            encoding_key,
+            decoding_keys,
+            jwks: JwkSet { keys: jwks },
+        })
+    }
 
+    /// Public keys of all the keys, to be published for other services to
+    /// verify tokens with.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock should be past the Unix epoch")
+        .as_secs()
+}
+
+/// Verifies the signature and claims of `token`. It must have been issued by
+/// `issuer` for `audience`, and be valid now: from `nbf` included to `exp`
+/// excluded, each widened by `leeway` seconds of tolerated clock skew.
 pub fn decode(
     token: &str,
-    secret: &[u8],
+    keys: &JwtKeys,
+    issuer: &str,
+    audience: &str,
+    leeway: u64,
 ) -> Result<TokenData<TokenClaims>, jsonwebtoken::errors::Error> {
-    jsonwebtoken::decode::<TokenClaims>(
-        &token,
-        &DecodingKey::from_secret(secret),
-        &Validation::default(),
-    )
+    decode_at(token, keys, issuer, audience, leeway, now())
 }
 
-pub fn encode(user_id: i32, secret: &[u8]) -> Result<String, jsonwebtoken::errors::Error> {
-    let exp_duration = 60 * 60 * 24 * 30 * 6;
+fn decode_at(
+    token: &str,
+    keys: &JwtKeys,
+    issuer: &str,
+    audience: &str,
+    leeway: u64,
+    now: u64,
+) -> Result<TokenData<TokenClaims>, jsonwebtoken::errors::Error> {
+    let header = jsonwebtoken::decode_header(token)?;
 
-    let exp = (SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
-        + exp_duration) as usize;
-
-    jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &TokenClaims { id: user_id, exp },
-        &jsonwebtoken::EncodingKey::from_secret(secret),
-    )
+    let key = header
+        .kid
+        .and_then(|kid| keys.decoding_keys.get(&kid))
+        .ok_or(ErrorKind::InvalidToken)?;
+
+    // The time claims are checked below, jsonwebtoken would still accept a
+    // token during the second of its `exp`.
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["sub", "iss", "aud", "nbf", "exp"]);
+    validation.validate_exp = false;
+    validation.validate_nbf = false;
+
+    let data = jsonwebtoken::decode::<TokenClaims>(token, key, &validation)?;
+
+    if now >= data.claims.exp.saturating_add(leeway) {
+        return Err(ErrorKind::ExpiredSignature.into());
+    }
+
+    if now.saturating_add(leeway) < data.claims.nbf {
+        return Err(ErrorKind::ImmatureSignature.into());
+    }
+
+    Ok(data)
+}
+
+/// Signs `claims` with the active key.
+pub fn encode<T: Serialize>(
+    claims: &T,
+    keys: &JwtKeys,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let header = Header {
+        kid: Some(keys.active_kid.clone()),
+        ..Header::new(Algorithm::EdDSA)
+    };
+
+    jsonwebtoken::encode(&header, claims, &keys.encoding_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ring::rand::SystemRandom;
+    use uuid::Uuid;
+
+    use super::*;
+
+    const ISSUER: &str = "https://gossip.test";
+    const AUDIENCE: &str = "gossip";
+
+    fn claims(issued_at: u64, ttl: u64) -> TokenClaims {
+        TokenClaims::new(1, Uuid::new_v4(), ISSUER, AUDIENCE, issued_at, ttl)
+    }
+
+    fn decode_now(token: &str, keys: &JwtKeys) -> jsonwebtoken::errors::Result<TokenClaims> {
+        decode(token, keys, ISSUER, AUDIENCE, 0).map(|data| data.claims)
+    }
+
+    fn single_key() -> JwtKeys {
+        let dir = key_dir(&[("a", &private_key_pem())]);
+        let keys = JwtKeys::load(&dir, "a").unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        keys
+    }
+
+    fn private_key_pem() -> String {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+
+        pem::encode(&pem::Pem::new("PRIVATE KEY", pkcs8.as_ref()))
+    }
+
+    fn public_key_pem(private_key_pem: &str) -> String {
+        let private_key = pem::parse(private_key_pem).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(private_key.contents()).unwrap();
+        let spki = [&ED25519_SPKI_PREFIX[..], key_pair.public_key().as_ref()].concat();
+
+        pem::encode(&pem::Pem::new("PUBLIC KEY", spki))
+    }
+
+    fn key_dir(keys: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gossip-keys-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (kid, pem) in keys {
+            fs::write(dir.join(format!("{kid}.pem")), pem).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn test_signs_with_active_key() {
+        let dir = key_dir(&[("old", &private_key_pem()), ("new", &private_key_pem())]);
+        let keys = JwtKeys::load(&dir, "new").unwrap();
+
+        let token = encode(&claims(now(), 60), &keys).unwrap();
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::EdDSA);
+        assert_eq!(header.kid.as_deref(), Some("new"));
+
+        assert_eq!(decode_now(&token, &keys).unwrap().user_id(), Some(1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_retired_keys_still_verify() {
+        let old = private_key_pem();
+        let dir = key_dir(&[("old", &old)]);
+        let token = encode(&claims(now(), 60), &JwtKeys::load(&dir, "old").unwrap()).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        // The private key of a retired key can be thrown away.
+        let dir = key_dir(&[("old", &public_key_pem(&old)), ("new", &private_key_pem())]);
+        let keys = JwtKeys::load(&dir, "new").unwrap();
+        assert_eq!(decode_now(&token, &keys).unwrap().user_id(), Some(1));
+
+        let kids = keys
+            .jwks()
+            .keys
+            .iter()
+            .map(|jwk| jwk.common.key_id.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(kids, ["new", "old"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unknown_keys() {
+        let dir = key_dir(&[("a", &private_key_pem())]);
+        let token = encode(&claims(now(), 60), &JwtKeys::load(&dir, "a").unwrap()).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        // Same kid, different key.
+        let dir = key_dir(&[("a", &private_key_pem())]);
+        assert!(decode_now(&token, &JwtKeys::load(&dir, "a").unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+
+        let dir = key_dir(&[("b", &private_key_pem())]);
+        assert!(decode_now(&token, &JwtKeys::load(&dir, "b").unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_active_key_must_be_private() {
+        let dir = key_dir(&[("a", &public_key_pem(&private_key_pem()))]);
+        assert!(JwtKeys::load(&dir, "a").is_err());
+        assert!(JwtKeys::load(&dir, "b").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_claims_are_in_seconds() {
+        let claims = claims(1_700_000_000, 900);
+
+        assert_eq!(claims.sub, "1");
+        assert_eq!(claims.iat, 1_700_000_000);
+        assert_eq!(claims.nbf, 1_700_000_000);
+        assert_eq!(claims.exp, 1_700_000_900);
+    }
+
+    #[test]
+    fn test_expires_at_exp() {
+        let keys = single_key();
+        let issued_at = now();
+        let token = encode(&claims(issued_at, 60), &keys).unwrap();
+        let decode_at = |now| decode_at(&token, &keys, ISSUER, AUDIENCE, 0, now);
+
+        assert!(decode_at(issued_at).is_ok());
+        assert!(decode_at(issued_at + 59).is_ok());
+        assert_eq!(
+            decode_at(issued_at + 60).unwrap_err().kind(),
+            &ErrorKind::ExpiredSignature
+        );
+        assert_eq!(
+            decode_at(issued_at - 1).unwrap_err().kind(),
+            &ErrorKind::ImmatureSignature
+        );
+    }
+
+    #[test]
+    fn test_leeway_widens_validity() {
+        let keys = single_key();
+        let issued_at = now();
+        let token = encode(&claims(issued_at, 60), &keys).unwrap();
+        let decode_at = |now| decode_at(&token, &keys, ISSUER, AUDIENCE, 5, now);
+
+        assert!(decode_at(issued_at - 5).is_ok());
+        assert!(decode_at(issued_at + 64).is_ok());
+        assert!(decode_at(issued_at - 6).is_err());
+        assert!(decode_at(issued_at + 65).is_err());
+    }
+
+    #[test]
+    fn test_enforces_issuer_and_audience() {
+        let keys = single_key();
+        let token = encode(&claims(now(), 60), &keys).unwrap();
+
+        assert!(decode(&token, &keys, ISSUER, AUDIENCE, 0).is_ok());
+        assert_eq!(
+            decode(&token, &keys, "https://evil.test", AUDIENCE, 0)
+                .unwrap_err()
+                .kind(),
+            &ErrorKind::InvalidIssuer
+        );
+        assert_eq!(
+            decode(&token, &keys, ISSUER, "other", 0)
+                .unwrap_err()
+                .kind(),
+            &ErrorKind::InvalidAudience
+        );
+    }
+
+    #[test]
+    fn test_tokens_are_unique() {
+        let issued_at = now();
+
+        assert_ne!(claims(issued_at, 60).jti, claims(issued_at, 60).jti);
+    }
 }
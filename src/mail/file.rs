@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use axum::async_trait;
+use uuid::Uuid;
+
+use super::{Email, Mailer, Sender};
+
+/// Writes every email as an `.eml` file into a directory, for local development.
+#[derive(Debug)]
+pub struct FileMailer {
+    dir: PathBuf,
+    sender: Sender,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf, sender: Sender) -> FileMailer {
+        FileMailer { dir, sender }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        let message = email.to_message(&self.sender).write_to_vec()?;
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.dir.join(format!("{}.eml", Uuid::new_v4())), message).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_eml_file() {
+        let dir = std::env::temp_dir().join(format!("gossip-mail-{}", Uuid::new_v4()));
+        let sender = Sender {
+            name: "Gossip".to_owned(),
+            address: "noreply@gossip.app".to_owned(),
+        };
+
+        FileMailer::new(dir.clone(), sender)
+            .send(Email::new("me", "a.b@c.com", "Hello", "<b>Hi!</b>", "Hi!"))
+            .await
+            .unwrap();
+
+        let mut entries = std::fs::read_dir(&dir).unwrap();
+        let path = entries.next().unwrap().unwrap().path();
+        assert!(entries.next().is_none());
+        assert_eq!(path.extension().unwrap(), "eml");
+
+        let message = std::fs::read_to_string(&path).unwrap();
+        assert!(message.contains("To: \"me\" <a.b@c.com>"));
+        assert!(message.contains("From: \"Gossip\" <noreply@gossip.app>"));
+        assert!(message.contains("Subject: Hello"));
+        assert!(message.contains("<b>Hi!</b>"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
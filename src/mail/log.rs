@@ -0,0 +1,21 @@
+use axum::async_trait;
+
+use super::{Email, Mailer};
+
+/// Logs emails instead of sending them.
+#[derive(Debug)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        tracing::info!(
+            to = email.to_address,
+            subject = email.subject,
+            body = email.text_body,
+            "Email not sent, logging only"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Mutex;
+
+use axum::async_trait;
+
+use super::{Email, Mailer};
+
+/// Keeps sent emails in memory so that tests can inspect them.
+#[derive(Debug, Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    /// Every email sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(email);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_captures_sent_emails() {
+        let mailer = MemoryMailer::default();
+
+        mailer
+            .send(Email::new("me", "a.b@c.com", "First", "1", "1"))
+            .await
+            .unwrap();
+        mailer
+            .send(Email::new("you", "d.e@f.com", "Second", "2", "2"))
+            .await
+            .unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].subject, "First");
+        assert_eq!(sent[1].to_address, "d.e@f.com");
+    }
+}
@@ -0,0 +1,84 @@
+mod file;
+mod log;
+#[cfg(test)]
+mod memory;
+mod smtp;
+mod templates;
+
+use std::{fmt::Debug, sync::Arc};
+
+use axum::async_trait;
+use mail_send::mail_builder::MessageBuilder;
+
+use crate::config::{Config, MailTransport};
+
+pub use file::FileMailer;
+pub use log::LogMailer;
+#[cfg(test)]
+pub use memory::MemoryMailer;
+pub use smtp::SmtpMailer;
+pub use templates::{is_valid_locale, Message, Templates};
+
+/// A transactional email addressed to a single recipient, with HTML and
+/// plain-text alternatives of its body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to_name: String,
+    pub to_address: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl Email {
+    pub fn new(
+        to_name: impl Into<String>,
+        to_address: impl Into<String>,
+        subject: impl Into<String>,
+        html_body: impl Into<String>,
+        text_body: impl Into<String>,
+    ) -> Email {
+        Email {
+            to_name: to_name.into(),
+            to_address: to_address.into(),
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+        }
+    }
+
+    fn to_message<'x>(&'x self, sender: &'x Sender) -> MessageBuilder<'x> {
+        MessageBuilder::new()
+            .from((sender.name.as_str(), sender.address.as_str()))
+            .to((self.to_name.as_str(), self.to_address.as_str()))
+            .subject(self.subject.as_str())
+            .html_body(self.html_body.as_str())
+            .text_body(self.text_body.as_str())
+    }
+}
+
+/// The `From` of every email sent by the app.
+#[derive(Debug, Clone)]
+pub struct Sender {
+    pub name: String,
+    pub address: String,
+}
+
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    async fn send(&self, email: Email) -> anyhow::Result<()>;
+}
+
+/// Builds the mailer selected by `MAIL_TRANSPORT`.
+pub fn from_config(config: &Config) -> Arc<dyn Mailer> {
+    let sender = Sender {
+        name: config.mail_author.clone(),
+        address: config.mail_email.clone(),
+    };
+
+    match &config.mail_transport {
+        MailTransport::Smtp(smtp) => Arc::new(SmtpMailer::new(smtp.clone(), sender)),
+        MailTransport::File(dir) => Arc::new(FileMailer::new(dir.clone(), sender)),
+        MailTransport::Log => Arc::new(LogMailer),
+    }
+}
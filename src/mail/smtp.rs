@@ -0,0 +1,36 @@
+use axum::async_trait;
+use mail_send::SmtpClientBuilder;
+
+use crate::config::SmtpConfig;
+
+use super::{Email, Mailer, Sender};
+
+/// Delivers email through an SMTP relay, connecting once per message.
+#[derive(Debug)]
+pub struct SmtpMailer {
+    config: SmtpConfig,
+    sender: Sender,
+}
+
+impl SmtpMailer {
+    pub fn new(config: SmtpConfig, sender: Sender) -> SmtpMailer {
+        SmtpMailer { config, sender }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> anyhow::Result<()> {
+        let config = &self.config;
+
+        SmtpClientBuilder::new(config.host.as_str(), config.port)
+            .implicit_tls(config.tls)
+            .credentials((config.username.as_str(), config.password.as_str()))
+            .connect()
+            .await?
+            .send(email.to_message(&self.sender))
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use anyhow::{bail, Context};
+use minijinja::{context, Environment, UndefinedBehavior, Value};
+
+use super::Email;
+
+/// A transactional message, along with the values its templates are rendered
+/// with. Every template can also use `name`, the name of the recipient.
+#[derive(Debug)]
+pub enum Message<'a> {
+    Verification {
+        code: &'a str,
+    },
+    Welcome,
+    PasswordReset {
+        token: &'a str,
+        /// Link to the page choosing the new password, if the app has one.
+        link: Option<String>,
+        expires_in_minutes: u64,
+    },
+    PasswordChanged,
+    EmailChangeVerification {
+        code: &'a str,
+    },
+    EmailChangeNotice {
+        new_email: &'a str,
+    },
+    EmailLogin {
+        code: &'a str,
+        /// Link logging in without typing the code, if the app has a page
+        /// for it.
+        link: Option<String>,
+        expires_in_minutes: u64,
+    },
+}
+
+impl Message<'_> {
+    /// Names of all messages, which is also the base name of their templates.
+    pub const NAMES: [&'static str; 7] = [
+        "verification",
+        "welcome",
+        "password_reset",
+        "password_changed",
+        "email_change_verification",
+        "email_change_notice",
+        "email_login",
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Message::Verification { .. } => "verification",
+            Message::Welcome => "welcome",
+            Message::PasswordReset { .. } => "password_reset",
+            Message::PasswordChanged => "password_changed",
+            Message::EmailChangeVerification { .. } => "email_change_verification",
+            Message::EmailChangeNotice { .. } => "email_change_notice",
+            Message::EmailLogin { .. } => "email_login",
+        }
+    }
+
+    fn context(&self) -> Value {
+        match self {
+            Message::Verification { code } | Message::EmailChangeVerification { code } => {
+                context! { code }
+            }
+            Message::Welcome | Message::PasswordChanged => context! {},
+            Message::PasswordReset {
+                token,
+                link,
+                expires_in_minutes,
+            } => context! { token, link, expires_in_minutes },
+            Message::EmailChangeNotice { new_email } => context! { new_email },
+            Message::EmailLogin {
+                code,
+                link,
+                expires_in_minutes,
+            } => context! { code, link, expires_in_minutes },
+        }
+    }
+
+    /// One of each message, with and without its optional values, to check
+    /// that every template renders.
+    fn samples() -> Vec<Message<'static>> {
+        vec![
+            Message::Verification { code: "123456" },
+            Message::Welcome,
+            Message::PasswordReset {
+                token: "t0k3n",
+                link: Some("https://gossip.app/reset?token=t0k3n".to_owned()),
+                expires_in_minutes: 30,
+            },
+            Message::PasswordReset {
+                token: "t0k3n",
+                link: None,
+                expires_in_minutes: 30,
+            },
+            Message::PasswordChanged,
+            Message::EmailChangeVerification { code: "123456" },
+            Message::EmailChangeNotice {
+                new_email: "new@def.com",
+            },
+            Message::EmailLogin {
+                code: "123456",
+                link: Some("https://gossip.app/login?token=t0k3n".to_owned()),
+                expires_in_minutes: 10,
+            },
+            Message::EmailLogin {
+                code: "123456",
+                link: None,
+                expires_in_minutes: 10,
+            },
+        ]
+    }
+}
+
+/// The email templates, loaded from a directory with one subdirectory per
+/// locale, such as `templates/en` or `templates/pt-br`.
+///
+/// Each message has three templates in a locale directory: the subject in
+/// `<name>.subject.txt` and the body alternatives in `<name>.html` and
+/// `<name>.txt`. Values are escaped in the HTML body only.
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+    locales: BTreeSet<String>,
+    fallback_locale: String,
+}
+
+impl Templates {
+    /// Loads every template in `dir`. Fails if a template doesn't parse or
+    /// render, or if `fallback_locale` lacks a template of any message.
+    pub fn load(dir: &Path, fallback_locale: &str) -> anyhow::Result<Templates> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+        let mut locales = BTreeSet::new();
+
+        for locale_dir in fs::read_dir(dir).with_context(|| format!("reading {dir:?}"))? {
+            let locale_dir = locale_dir?;
+
+            if !locale_dir.file_type()?.is_dir() {
+                continue;
+            }
+
+            let locale = locale_dir.file_name().to_string_lossy().to_lowercase();
+
+            for file in fs::read_dir(locale_dir.path())? {
+                let file = file?;
+                let path = file.path();
+                let source =
+                    fs::read_to_string(&path).with_context(|| format!("reading {path:?}"))?;
+                let name = format!("{locale}/{}", file.file_name().to_string_lossy());
+
+                env.add_template_owned(name, source)
+                    .with_context(|| format!("parsing {path:?}"))?;
+            }
+
+            locales.insert(locale);
+        }
+
+        let templates = Templates {
+            env,
+            locales,
+            fallback_locale: fallback_locale.to_lowercase(),
+        };
+
+        for message in Message::NAMES {
+            for part in ["subject.txt", "html", "txt"] {
+                let name = format!("{}/{message}.{part}", templates.fallback_locale);
+
+                if templates.env.get_template(&name).is_err() {
+                    bail!("The fallback locale is missing the {name} template");
+                }
+            }
+        }
+
+        // Rendering is strict about undefined values, so a template using one
+        // would otherwise only fail once a request sends it.
+        for locale in &templates.locales {
+            for message in Message::samples() {
+                let context = context! { name => "Ann", ..message.context() };
+
+                for part in ["subject.txt", "html", "txt"] {
+                    let name = format!("{locale}/{}.{part}", message.name());
+
+                    if let Ok(template) = templates.env.get_template(&name) {
+                        template
+                            .render(&context)
+                            .with_context(|| format!("rendering {name}"))?;
+                    }
+                }
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// Locales that have templates.
+    #[cfg(test)]
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.locales.iter().map(String::as_str)
+    }
+
+    /// Renders `message` in the language closest to `locale` that has the
+    /// templates of the message: the locale itself, then its base language, and
+    /// the fallback locale when neither does.
+    pub fn render(
+        &self,
+        message: &Message,
+        locale: Option<&str>,
+        to_name: &str,
+        to_address: &str,
+    ) -> Email {
+        let locale = self.resolve(message.name(), locale);
+        let context = context! { name => to_name, ..message.context() };
+
+        let render = |part: &str| {
+            self.env
+                .get_template(&format!("{locale}/{}.{part}", message.name()))
+                .and_then(|template| template.render(&context))
+                .expect("Email templates were checked to render when loaded")
+        };
+
+        Email::new(
+            to_name,
+            to_address,
+            render("subject.txt").trim(),
+            render("html"),
+            render("txt"),
+        )
+    }
+
+    fn resolve(&self, message: &str, locale: Option<&str>) -> &str {
+        let Some(locale) = locale.map(str::to_lowercase) else {
+            return &self.fallback_locale;
+        };
+
+        let language = locale.split('-').next().unwrap_or_default().to_owned();
+
+        [locale, language]
+            .into_iter()
+            .find_map(|candidate| {
+                let candidate = self.locales.get(&candidate)?;
+
+                ["subject.txt", "html", "txt"]
+                    .iter()
+                    .all(|part| {
+                        self.env
+                            .get_template(&format!("{candidate}/{message}.{part}"))
+                            .is_ok()
+                    })
+                    .then_some(candidate.as_str())
+            })
+            .unwrap_or(&self.fallback_locale)
+    }
+}
+
+/// Whether `locale` looks like a BCP 47 language tag, such as `en` or `es-MX`.
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+
+    let language_ok = subtags.next().is_some_and(|language| {
+        (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic())
+    });
+
+    language_ok
+        && locale.len() <= 35
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn templates() -> Templates {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+
+        Templates::load(&dir, "en").unwrap()
+    }
+
+    #[test]
+    fn test_renders_every_template() {
+        let templates = templates();
+        assert!(templates.locales().count() > 1);
+
+        for locale in templates.locales() {
+            for message in Message::samples() {
+                let email = templates.render(&message, Some(locale), "Ann", "a.b@c.com");
+
+                assert_eq!(email.to_name, "Ann");
+                assert_eq!(email.to_address, "a.b@c.com");
+                assert!(!email.subject.is_empty(), "{locale} {message:?}");
+                assert!(!email.subject.contains('\n'), "{locale} {message:?}");
+                assert!(email.html_body.contains("Ann"), "{locale} {message:?}");
+                assert!(email.text_body.contains("Ann"), "{locale} {message:?}");
+                assert!(!email.text_body.contains('<'), "{locale} {message:?}");
+
+                let expected = match &message {
+                    Message::Verification { code }
+                    | Message::EmailChangeVerification { code }
+                    | Message::EmailLogin { code, .. } => Some(*code),
+                    Message::PasswordReset { token, .. } => Some(*token),
+                    Message::EmailChangeNotice { new_email } => Some(*new_email),
+                    _ => None,
+                };
+
+                if let Some(expected) = expected {
+                    assert!(email.html_body.contains(expected), "{locale} {message:?}");
+                    assert!(email.text_body.contains(expected), "{locale} {message:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_templates_with_undefined_values() {
+        let dir = std::env::temp_dir().join(format!("gossip-templates-{}", Uuid::new_v4()));
+        let en = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/en");
+        fs::create_dir_all(dir.join("en")).unwrap();
+        fs::create_dir_all(dir.join("de")).unwrap();
+
+        for file in fs::read_dir(en).unwrap() {
+            let file = file.unwrap();
+            fs::copy(file.path(), dir.join("en").join(file.file_name())).unwrap();
+        }
+
+        fs::write(dir.join("de/welcome.txt"), "Hallo {{ nmae }}!").unwrap();
+
+        let result = Templates::load(&dir, "en");
+        fs::remove_dir_all(dir).unwrap();
+
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(error.contains("de/welcome.txt"), "{error}");
+    }
+
+    #[test]
+    fn test_every_locale_has_every_template() {
+        let templates = templates();
+
+        for locale in templates.locales() {
+            for message in Message::NAMES {
+                assert_eq!(templates.resolve(message, Some(locale)), locale);
+            }
+        }
+    }
+
+    #[test]
+    fn test_falls_back_to_closest_locale() {
+        let templates = templates();
+        let message = Message::Welcome;
+
+        assert_eq!(templates.resolve(message.name(), Some("es")), "es");
+        assert_eq!(templates.resolve(message.name(), Some("ES-mx")), "es");
+        assert_eq!(templates.resolve(message.name(), Some("xx")), "en");
+        assert_eq!(templates.resolve(message.name(), None), "en");
+
+        let spanish = templates.render(&message, Some("es-MX"), "Ann", "a.b@c.com");
+        let english = templates.render(&message, None, "Ann", "a.b@c.com");
+        assert_ne!(spanish.subject, english.subject);
+    }
+
+    #[test]
+    fn test_escapes_html_only() {
+        let email = templates().render(&Message::Welcome, None, "<Ann & Bob>", "a.b@c.com");
+
+        assert!(email.html_body.contains("&lt;Ann &amp; Bob&gt;"));
+        assert!(email.text_body.contains("<Ann & Bob>"));
+    }
+
+    #[test]
+    fn test_validates_locales() {
+        assert!(is_valid_locale("en"));
+        assert!(is_valid_locale("es-MX"));
+        assert!(is_valid_locale("zh-Hant-TW"));
+
+        assert!(!is_valid_locale(""));
+        assert!(!is_valid_locale("e"));
+        assert!(!is_valid_locale("en_US"));
+        assert!(!is_valid_locale("en-"));
+        assert!(!is_valid_locale("../en"));
+    }
+}
@@ -1,18 +1,25 @@
+mod blob_store;
 mod config;
 mod db;
+mod error;
+mod extract;
 mod features;
 mod jwt;
+mod mail;
 mod openapi;
+mod rate_limit;
 mod state;
+mod token;
+mod totp;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{Router, Server};
 
 use config::Config;
 use openapi::ApiDoc;
 use state::AppState;
-use tower_http::trace::TraceLayer;
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
@@ -20,6 +27,14 @@ fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
         .nest("/user", features::users::router(state.clone()))
         .nest("/auth", features::auth::router(state.clone()))
+        .nest("/auth/oidc", features::oidc::router(state.clone()))
+        .nest(
+            "/auth/tokens",
+            features::access_tokens::router(state.clone()),
+        )
+        .nest("/ops", features::outbox::router(state.clone()))
+        .nest("/.well-known", features::auth::well_known_router())
+        .nest_service("/blobs", ServeDir::new(&state.config.blob_dir))
         .merge(
             SwaggerUi::new("/api-docs/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()),
         )
@@ -27,24 +42,37 @@ fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
 
 #[tokio::main]
 async fn main() {
-    match dotenvy::dotenv() {
-        Err(e) => eprintln!("Warning: .env file failed to load: {}", e),
-        _ => {}
-    };
+    if let Err(e) = dotenvy::dotenv() {
+        eprintln!("Warning: .env file failed to load: {}", e);
+    }
 
     let config = Config::from_env();
     let db = db::db_connect(&config.db_url).await;
-    let state = Arc::new(AppState::new(db, config));
+    let mailer = mail::from_config(&config);
+    let templates = mail::Templates::load(&config.templates_dir, &config.default_locale)
+        .expect("Failed to load email templates");
+    let jwt_keys = jwt::JwtKeys::load(&config.jwt_keys_dir, &config.jwt_active_kid)
+        .expect("Failed to load JWT keys");
+    let state = Arc::new(AppState::new(
+        db,
+        config,
+        mailer,
+        Arc::new(templates),
+        Arc::new(jwt_keys),
+    ));
 
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
+    tokio::spawn(features::outbox::worker::run(state.clone()));
+    tokio::spawn(rate_limit::run_pruner(state.rate_limiter.clone()));
+
     let app = router(state.clone())
         .with_state(state)
         .layer(TraceLayer::new_for_http())
-        .into_make_service();
+        .into_make_service_with_connect_info::<SocketAddr>();
 
-    let addr = (([0, 0, 0, 0], (8000))).into();
+    let addr = ([0, 0, 0, 0], 8000).into();
     Server::bind(&addr).serve(app).await.unwrap();
 }
@@ -9,23 +9,98 @@ use utoipa::{
         crate::features::auth::routes::login,
         crate::features::auth::routes::register,
         crate::features::auth::routes::verify_email,
+        crate::features::auth::routes::resend_verification_code,
+        crate::features::auth::routes::refresh,
+        crate::features::auth::routes::logout,
+        crate::features::auth::routes::logout_all,
+        crate::features::auth::routes::list_sessions,
+        crate::features::auth::routes::revoke_session,
+        crate::features::auth::routes::request_password_reset,
+        crate::features::auth::routes::confirm_password_reset,
+        crate::features::auth::routes::change_password,
+        crate::features::auth::routes::login_two_factor,
+        crate::features::auth::routes::request_email_login,
+        crate::features::auth::routes::confirm_email_login,
+        crate::features::auth::routes::enroll_totp,
+        crate::features::auth::routes::confirm_totp,
+        crate::features::auth::routes::disable_two_factor,
+        crate::features::auth::routes::jwks,
+        crate::features::oidc::routes::start,
+        crate::features::oidc::routes::callback,
+        crate::features::access_tokens::routes::create_token,
+        crate::features::access_tokens::routes::list_tokens,
+        crate::features::access_tokens::routes::revoke_token,
+
+        crate::features::outbox::routes::outbox_report,
+        crate::features::outbox::routes::retry_dead_letter,
 
         crate::features::users::routes::user_by_id,
         crate::features::users::routes::user_by_email,
+        crate::features::users::routes::search,
+        crate::features::users::routes::user_by_handle,
+        crate::features::users::routes::handle_availability,
         crate::features::users::routes::me,
+        crate::features::users::routes::update_me,
+        crate::features::users::routes::privacy,
+        crate::features::users::routes::update_privacy,
+        crate::features::users::routes::change_handle,
+        crate::features::users::routes::upload_avatar,
+        crate::features::users::routes::delete_avatar,
+        crate::features::users::routes::upload_banner,
+        crate::features::users::routes::delete_banner,
+        crate::features::users::routes::change_email,
+        crate::features::users::routes::confirm_email_change,
     ),
     components(schemas(
+        crate::error::ProblemDetails,
+        crate::error::ErrorCode,
+
         crate::features::auth::models::LoginRequest,
         crate::features::auth::models::LoginResponse,
+        crate::features::auth::models::Session,
+        crate::features::auth::models::Scope,
+        crate::features::access_tokens::models::CreateAccessTokenRequest,
+        crate::features::access_tokens::models::PersonalAccessToken,
+        crate::features::access_tokens::models::CreatedAccessToken,
+        crate::features::auth::models::RefreshRequest,
+        crate::features::auth::models::PasswordResetRequest,
+        crate::features::auth::models::PasswordResetConfirmRequest,
+        crate::features::auth::models::ChangePasswordRequest,
         crate::features::auth::models::RegisterRequest,
         crate::features::auth::models::VerifyEmailRequest,
+        crate::features::auth::models::ResendVerificationRequest,
+        crate::features::auth::models::EmailLoginRequest,
+        crate::features::auth::models::EmailLoginConfirmRequest,
+        crate::features::auth::models::TwoFactorChallenge,
+        crate::features::auth::models::TwoFactorLoginRequest,
+        crate::features::auth::models::TotpEnrollment,
+        crate::features::auth::models::ConfirmTotpRequest,
+        crate::features::auth::models::RecoveryCodes,
+        crate::features::auth::models::DisableTwoFactorRequest,
+
+        crate::features::outbox::models::OutboxReport,
+        crate::features::outbox::models::OutboxStatus,
+        crate::features::outbox::models::DeadLetter,
 
         crate::features::users::models::UserProfile,
+        crate::features::users::models::UpdateProfileRequest,
+        crate::features::users::models::Visibility,
+        crate::features::users::models::PrivacySettings,
+        crate::features::users::models::UpdatePrivacyRequest,
+        crate::features::users::models::ProfileImage,
+        crate::features::users::models::UserSearchPage,
+        crate::features::users::models::ImageUpload,
+        crate::features::users::models::ChangeHandleRequest,
+        crate::features::users::models::HandleAvailability,
+        crate::features::users::models::HandleUnavailableReason,
+        crate::features::users::models::ChangeEmailRequest,
+        crate::features::users::models::ConfirmEmailChangeRequest,
     )),
     modifiers(&SecurityAddon),
     tags(
         (name = "auth",),
-        (name = "users",)
+        (name = "users",),
+        (name = "ops",)
     )
 )]
 pub struct ApiDoc;
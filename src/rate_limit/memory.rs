@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+
+use crate::config::RateLimit;
+
+use super::{refill, retry_after, Lockout, RateLimitStore};
+
+/// Keeps rate limits in the process, so each instance limits on its own.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// When the bucket is full again, and can be forgotten.
+    full_at: Instant,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: i32,
+    locked_until: Option<Instant>,
+    /// When the failures are forgotten.
+    expires_at: Instant,
+}
+
+impl MemoryStore {
+    fn take_at(&self, key: &str, limit: RateLimit, now: Instant) -> Option<u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let tokens = match buckets.get(key) {
+            Some(bucket) => refill(
+                bucket.tokens,
+                (now - bucket.updated_at).as_secs_f64(),
+                limit,
+            ),
+            None => limit.requests as f64,
+        };
+
+        if tokens < 1.0 {
+            return Some(retry_after(tokens, limit));
+        }
+
+        let tokens = tokens - 1.0;
+        let until_full = (limit.requests as f64 - tokens) / limit.refill_rate();
+
+        buckets.insert(
+            key.to_owned(),
+            Bucket {
+                tokens,
+                updated_at: now,
+                full_at: now + Duration::from_secs_f64(until_full),
+            },
+        );
+
+        None
+    }
+
+    fn record_failure_at(&self, key: &str, lockout: Lockout, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+        let window = Duration::from_secs(lockout.seconds);
+
+        let entry = failures.entry(key.to_owned()).or_insert(Failures {
+            count: 0,
+            locked_until: None,
+            expires_at: now,
+        });
+
+        if entry.expires_at <= now {
+            entry.count = 0;
+        }
+
+        entry.count += 1;
+        entry.expires_at = now + window;
+
+        if entry.count >= lockout.threshold {
+            entry.count = 0;
+            entry.locked_until = Some(now + window);
+        }
+    }
+
+    fn locked_for_at(&self, key: &str, now: Instant) -> Option<u64> {
+        let failures = self.failures.lock().unwrap();
+
+        failures
+            .get(key)
+            .and_then(|failures| failures.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| (locked_until - now).as_secs_f64().ceil() as u64)
+    }
+
+    fn prune_at(&self, now: Instant) {
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| bucket.full_at > now);
+        self.failures
+            .lock()
+            .unwrap()
+            .retain(|_, failures| failures.expires_at > now);
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, limit: RateLimit) -> anyhow::Result<Option<u64>> {
+        Ok(self.take_at(key, limit, Instant::now()))
+    }
+
+    async fn record_failure(&self, key: &str, lockout: Lockout) -> anyhow::Result<()> {
+        self.record_failure_at(key, lockout, Instant::now());
+
+        Ok(())
+    }
+
+    async fn locked_for(&self, key: &str) -> anyhow::Result<Option<u64>> {
+        Ok(self.locked_for_at(key, Instant::now()))
+    }
+
+    async fn clear_failures(&self, key: &str) -> anyhow::Result<()> {
+        self.failures.lock().unwrap().remove(key);
+
+        Ok(())
+    }
+
+    async fn prune(&self) -> anyhow::Result<()> {
+        self.prune_at(Instant::now());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        requests: 3,
+        seconds: 30,
+    };
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let store = MemoryStore::default();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(store.take_at("key", LIMIT, start), None);
+        }
+        assert_eq!(store.take_at("key", LIMIT, start), Some(10));
+        assert_eq!(
+            store.take_at("key", LIMIT, start + Duration::from_secs(4)),
+            Some(6)
+        );
+
+        // One request every 10 seconds.
+        let later = start + Duration::from_secs(10);
+        assert_eq!(store.take_at("key", LIMIT, later), None);
+        assert_eq!(store.take_at("key", LIMIT, later), Some(10));
+
+        assert_eq!(store.take_at("other", LIMIT, later), None);
+    }
+
+    #[test]
+    fn failures_lock_out_and_expire() {
+        let store = MemoryStore::default();
+        let lockout = Lockout {
+            threshold: 3,
+            seconds: 60,
+        };
+        let start = Instant::now();
+
+        store.record_failure_at("key", lockout, start);
+        store.record_failure_at("key", lockout, start);
+
+        // Failures further apart than the window don't add up.
+        let later = start + Duration::from_secs(61);
+        store.record_failure_at("key", lockout, later);
+        assert_eq!(store.locked_for_at("key", later), None);
+
+        store.record_failure_at("key", lockout, later);
+        store.record_failure_at("key", lockout, later);
+        assert_eq!(store.locked_for_at("key", later), Some(60));
+        assert_eq!(
+            store.locked_for_at("key", later + Duration::from_secs(45)),
+            Some(15)
+        );
+        assert_eq!(
+            store.locked_for_at("key", later + Duration::from_secs(60)),
+            None
+        );
+    }
+
+    #[test]
+    fn prunes_full_buckets_and_expired_failures() {
+        let store = MemoryStore::default();
+        let lockout = Lockout {
+            threshold: 3,
+            seconds: 60,
+        };
+        let start = Instant::now();
+
+        store.take_at("key", LIMIT, start);
+        store.record_failure_at("key", lockout, start);
+
+        store.prune_at(start + Duration::from_secs(5));
+        assert_eq!(store.buckets.lock().unwrap().len(), 1);
+        assert_eq!(store.failures.lock().unwrap().len(), 1);
+
+        store.prune_at(start + Duration::from_secs(60));
+        assert!(store.buckets.lock().unwrap().is_empty());
+        assert!(store.failures.lock().unwrap().is_empty());
+    }
+}
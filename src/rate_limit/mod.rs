@@ -0,0 +1,305 @@
+//! Throttling of the routes open to brute force and abuse, with token buckets
+//! keyed by client IP and by target email, and lockout of emails after
+//! repeated failed logins.
+
+mod memory;
+mod postgres;
+
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use axum::async_trait;
+
+use crate::{
+    config::{Config, RateLimit, RateLimitStorage, RateLimits},
+    db::Db,
+    error::AppError,
+};
+
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+
+/// How often stores forget full buckets and expired failures.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Consecutive failures after which a key is locked out, and for how long.
+#[derive(Debug, Clone, Copy)]
+pub struct Lockout {
+    pub threshold: i32,
+    pub seconds: u64,
+}
+
+#[async_trait]
+pub trait RateLimitStore: Debug + Send + Sync {
+    /// Takes a request from the bucket of `key`. Returns `None` if there was
+    /// one, or the seconds until there is.
+    async fn take(&self, key: &str, limit: RateLimit) -> anyhow::Result<Option<u64>>;
+
+    /// Counts a failure of `key`, and locks it out once there were
+    /// `lockout.threshold` of them less than `lockout.seconds` apart.
+    async fn record_failure(&self, key: &str, lockout: Lockout) -> anyhow::Result<()>;
+
+    /// Seconds left of the lockout of `key`, if it is locked out.
+    async fn locked_for(&self, key: &str) -> anyhow::Result<Option<u64>>;
+
+    async fn clear_failures(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Forgets full buckets and expired failures.
+    async fn prune(&self) -> anyhow::Result<()>;
+}
+
+/// The routes with their own limits, see `RateLimits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Login,
+    Register,
+    Verify,
+    PasswordReset,
+    EmailLogin,
+}
+
+impl Route {
+    fn name(&self) -> &'static str {
+        match self {
+            Route::Login => "login",
+            Route::Register => "register",
+            Route::Verify => "verify",
+            Route::PasswordReset => "password_reset",
+            Route::EmailLogin => "email_login",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limits: RateLimits,
+    lockout: Lockout,
+}
+
+/// Builds the rate limiter selected by `RATE_LIMIT_STORAGE`.
+pub fn from_config(config: &Config, db: Db) -> RateLimiter {
+    let store: Arc<dyn RateLimitStore> = match config.rate_limit_storage {
+        RateLimitStorage::Memory => Arc::new(MemoryStore::default()),
+        RateLimitStorage::Postgres => Arc::new(PostgresStore { db }),
+    };
+
+    RateLimiter::new(
+        store,
+        config.rate_limits,
+        Lockout {
+            threshold: config.login_lockout_threshold,
+            seconds: config.login_lockout_duration,
+        },
+    )
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, limits: RateLimits, lockout: Lockout) -> Self {
+        RateLimiter {
+            store,
+            limits,
+            lockout,
+        }
+    }
+
+    /// Takes a request of `route` from the buckets of the client IP and of the
+    /// email it targets, failing with `RetryLater` if either is empty.
+    pub async fn check(
+        &self,
+        route: Route,
+        ip_address: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), AppError> {
+        let limit = self.limit(route);
+        let keys = [
+            ip_address.map(|ip| format!("{}:ip:{ip}", route.name())),
+            email.map(|email| format!("{}:email:{}", route.name(), normalize_email(email))),
+        ];
+
+        for key in keys.iter().flatten() {
+            if let Some(retry_after) = self.store.take(key, limit).await? {
+                tracing::info!("Rate limited {}", key);
+
+                return Err(AppError::RetryLater {
+                    retry_after: retry_after as i64,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `RetryLater` while logins to `email` are locked out.
+    pub async fn check_lockout(&self, email: &str) -> Result<(), AppError> {
+        match self.store.locked_for(&lockout_key(email)).await? {
+            Some(retry_after) => Err(AppError::RetryLater {
+                retry_after: retry_after as i64,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed login to `email`, whether it has an account or not.
+    pub async fn record_login_failure(&self, email: &str) -> Result<(), AppError> {
+        self.store
+            .record_failure(&lockout_key(email), self.lockout)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn clear_login_failures(&self, email: &str) -> Result<(), AppError> {
+        self.store.clear_failures(&lockout_key(email)).await?;
+
+        Ok(())
+    }
+
+    fn limit(&self, route: Route) -> RateLimit {
+        match route {
+            Route::Login => self.limits.login,
+            Route::Register => self.limits.register,
+            Route::Verify => self.limits.verify,
+            Route::PasswordReset => self.limits.password_reset,
+            Route::EmailLogin => self.limits.email_login,
+        }
+    }
+}
+
+/// Prunes the store of the rate limiter until the process exits.
+pub async fn run_pruner(rate_limiter: Arc<RateLimiter>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = rate_limiter.store.prune().await {
+            tracing::error!("Failed to prune rate limits: {:?}", e);
+        }
+    }
+}
+
+fn lockout_key(email: &str) -> String {
+    format!("login:lockout:{}", normalize_email(email))
+}
+
+/// So that `Ann@c.com ` and `ann@c.com` share their limits.
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Requests in a bucket that held `tokens` after `elapsed` more seconds.
+fn refill(tokens: f64, elapsed: f64, limit: RateLimit) -> f64 {
+    (tokens + elapsed * limit.refill_rate()).min(limit.requests as f64)
+}
+
+/// Seconds until a bucket holding `tokens` has a whole request again.
+fn retry_after(tokens: f64, limit: RateLimit) -> u64 {
+    ((1.0 - tokens) / limit.refill_rate()).ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limiter() -> RateLimiter {
+        let limit = RateLimit {
+            requests: 2,
+            seconds: 60,
+        };
+
+        RateLimiter::new(
+            Arc::new(MemoryStore::default()),
+            RateLimits {
+                login: limit,
+                register: limit,
+                verify: limit,
+                password_reset: limit,
+                email_login: limit,
+            },
+            Lockout {
+                threshold: 3,
+                seconds: 60,
+            },
+        )
+    }
+
+    #[test]
+    fn refills_up_to_the_limit() {
+        let limit = RateLimit {
+            requests: 10,
+            seconds: 60,
+        };
+
+        assert_eq!(refill(0.0, 6.0, limit), 1.0);
+        assert_eq!(refill(5.0, 3600.0, limit), 10.0);
+        assert_eq!(retry_after(0.0, limit), 6);
+        assert_eq!(retry_after(0.5, limit), 3);
+        assert_eq!(retry_after(0.99, limit), 1);
+    }
+
+    #[tokio::test]
+    async fn limits_by_ip_and_by_email() {
+        let rate_limiter = rate_limiter();
+        let check = |ip, email| rate_limiter.check(Route::Login, Some(ip), Some(email));
+
+        check("10.0.0.1", "ann@c.com").await.unwrap();
+        check("10.0.0.1", "bob@c.com").await.unwrap();
+
+        // The IP is out of requests, whatever the email.
+        let result = check("10.0.0.1", "eve@c.com").await;
+        assert!(matches!(
+            result,
+            Err(AppError::RetryLater { retry_after: 30 })
+        ));
+
+        // Ann's bucket has one request left, which spaces out the logins to
+        // her account from any IP.
+        check("10.0.0.2", " Ann@c.com").await.unwrap();
+        let result = check("10.0.0.3", "ann@c.com").await;
+        assert!(matches!(result, Err(AppError::RetryLater { .. })));
+
+        // Routes have their own buckets.
+        rate_limiter
+            .check(Route::Register, Some("10.0.0.1"), Some("ann@c.com"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn locks_out_after_repeated_failures() {
+        let rate_limiter = rate_limiter();
+
+        for _ in 0..2 {
+            rate_limiter
+                .record_login_failure("ann@c.com")
+                .await
+                .unwrap();
+        }
+        rate_limiter.check_lockout("ann@c.com").await.unwrap();
+
+        // A successful login starts the count over.
+        rate_limiter
+            .clear_login_failures("ann@c.com")
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            rate_limiter
+                .record_login_failure("ann@c.com")
+                .await
+                .unwrap();
+        }
+        rate_limiter.check_lockout("ann@c.com").await.unwrap();
+
+        rate_limiter
+            .record_login_failure("ANN@c.com")
+            .await
+            .unwrap();
+        let result = rate_limiter.check_lockout("ann@c.com").await;
+        assert!(matches!(
+            result,
+            Err(AppError::RetryLater { retry_after: 60 })
+        ));
+
+        rate_limiter.check_lockout("bob@c.com").await.unwrap();
+    }
+}
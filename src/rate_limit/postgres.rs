@@ -0,0 +1,147 @@
+use axum::async_trait;
+
+use crate::{config::RateLimit, db::Db};
+
+use super::{retry_after, Lockout, RateLimitStore};
+
+/// Keeps rate limits in the database, shared by every instance.
+#[derive(Debug)]
+pub struct PostgresStore {
+    pub db: Db,
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    async fn take(&self, key: &str, limit: RateLimit) -> anyhow::Result<Option<u64>> {
+        let bucket = sqlx::query_file!(
+            "queries/rate_limit/take.sql",
+            key,
+            limit.requests as f64,
+            limit.refill_rate()
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        if bucket.taken {
+            return Ok(None);
+        }
+
+        Ok(Some(retry_after(bucket.tokens, limit)))
+    }
+
+    async fn record_failure(&self, key: &str, lockout: Lockout) -> anyhow::Result<()> {
+        sqlx::query_file!(
+            "queries/rate_limit/record_failure.sql",
+            key,
+            lockout.threshold,
+            lockout.seconds as f64
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn locked_for(&self, key: &str) -> anyhow::Result<Option<u64>> {
+        let seconds = sqlx::query_file_scalar!("queries/rate_limit/locked_for.sql", key)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(seconds.map(|seconds| seconds as u64))
+    }
+
+    async fn clear_failures(&self, key: &str) -> anyhow::Result<()> {
+        sqlx::query_file!("queries/rate_limit/clear_failures.sql", key)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn prune(&self) -> anyhow::Result<()> {
+        sqlx::query_file!("queries/rate_limit/prune.sql")
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        requests: 3,
+        seconds: 30,
+    };
+
+    #[sqlx::test]
+    async fn test_bucket_empties_and_refills(pool: PgPool) {
+        let store = PostgresStore { db: pool.clone() };
+
+        for _ in 0..3 {
+            assert_eq!(store.take("key", LIMIT).await.unwrap(), None);
+        }
+        assert_eq!(store.take("key", LIMIT).await.unwrap(), Some(10));
+        assert_eq!(store.take("other", LIMIT).await.unwrap(), None);
+
+        // As if 5 seconds passed since the bucket was last taken from.
+        sqlx::query!("UPDATE rate_limit_bucket SET updated_at = updated_at - interval '5 seconds'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(store.take("key", LIMIT).await.unwrap(), Some(5));
+
+        sqlx::query!(
+            "UPDATE rate_limit_bucket SET updated_at = updated_at - interval '10 seconds'"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(store.take("key", LIMIT).await.unwrap(), None);
+        assert!(store.take("key", LIMIT).await.unwrap().is_some());
+    }
+
+    #[sqlx::test]
+    async fn test_failures_lock_out(pool: PgPool) {
+        let store = PostgresStore { db: pool.clone() };
+        let lockout = Lockout {
+            threshold: 3,
+            seconds: 60,
+        };
+
+        for _ in 0..2 {
+            store.record_failure("key", lockout).await.unwrap();
+        }
+        assert_eq!(store.locked_for("key").await.unwrap(), None);
+
+        store.clear_failures("key").await.unwrap();
+        for _ in 0..2 {
+            store.record_failure("key", lockout).await.unwrap();
+        }
+        assert_eq!(store.locked_for("key").await.unwrap(), None);
+
+        store.record_failure("key", lockout).await.unwrap();
+        assert_eq!(store.locked_for("key").await.unwrap(), Some(60));
+        assert_eq!(store.locked_for("other").await.unwrap(), None);
+
+        // Expired failures are forgotten.
+        sqlx::query!(
+            "UPDATE rate_limit_failure
+             SET locked_until = now(), expires_at = now() - interval '1 second'",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        store.prune().await.unwrap();
+
+        let left = sqlx::query_scalar!("SELECT COUNT(*) FROM rate_limit_failure")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(left, Some(0));
+    }
+}
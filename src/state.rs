@@ -1,13 +1,51 @@
-use crate::{config::Config, db::Db};
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    blob_store::{self, BlobStore},
+    config::Config,
+    db::Db,
+    jwt::JwtKeys,
+    mail::{Mailer, Templates},
+    rate_limit::{self, RateLimiter},
+};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db: Db,
     pub config: Config,
+    pub mailer: Arc<dyn Mailer>,
+    pub templates: Arc<Templates>,
+    pub jwt_keys: Arc<JwtKeys>,
+    /// Client of the outgoing HTTP requests, such as to OpenID Connect
+    /// providers.
+    pub http: reqwest::Client,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub blob_store: Arc<dyn BlobStore>,
 }
 
 impl AppState {
-    pub fn new(db: Db, config: Config) -> AppState {
-        AppState { db, config }
+    pub fn new(
+        db: Db,
+        config: Config,
+        mailer: Arc<dyn Mailer>,
+        templates: Arc<Templates>,
+        jwt_keys: Arc<JwtKeys>,
+    ) -> AppState {
+        let rate_limiter = Arc::new(rate_limit::from_config(&config, db.clone()));
+        let blob_store = blob_store::from_config(&config);
+
+        AppState {
+            db,
+            config,
+            mailer,
+            templates,
+            jwt_keys,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to build the HTTP client"),
+            rate_limiter,
+            blob_store,
+        }
     }
 }